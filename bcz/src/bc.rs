@@ -1,53 +1,381 @@
-use std::{sync::{Arc, RwLock}, time::Duration, error::Error};
+use std::{collections::HashMap, future::Future, sync::{Arc, RwLock, RwLockReadGuard}, time::Duration};
 
+use futures::{future, stream, FutureExt, Stream, StreamExt, TryStreamExt};
 use reqwest::{Client, Url};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
 
-pub use crate::bc_artist_directory::Artists;
-use crate::{ScraperState, bc_artist_page::{Releases, ReleaseUrl}, bc_artist_directory::ArtistUrl, RuntimeScraperState, ArtistDiscography};
+pub use crate::bc_artist_directory::{Artists, ArtistUrl};
+pub use crate::bc_artist_page::{Releases, ReleaseUrl};
+use crate::{ScraperState, fetch::{Fetcher, ReqwestFetcher}, bc_artist_directory::parse_custom_domain_page, bc_release_page::parse_release_page, cache::{CachePolicy, HttpCache, PageKind}, checkpoint::{CheckpointPolicy, Checkpointer}, error::{Error, Result}, http::HttpClient, rate_limit::{RateLimit, RateLimiter}, retry::RetryPolicy, warc::{WarcPolicy, WarcWriter}, RuntimeScraperState, ArtistDiscography, ArtistInfo, Release, ReleaseClassifier};
 
 pub struct BcScraper {
-    pub(crate) client: Client,
+    pub(crate) http: HttpClient,
+    pub(crate) artist_index_url: Url,
+    // Path of the discography page on each artist's own site.
+    pub(crate) discography_path: String,
     pub(crate) state: RuntimeScraperState,
+    pub(crate) checkpointer: Option<Arc<Checkpointer>>,
+    pub(crate) classifier: ReleaseClassifier,
+}
+
+// How much of the crawl happens at the same time.
+#[derive(Clone, Copy, Debug)]
+pub struct CrawlLimits {
+    // Artists being scraped at once, each fetching its discography page and then its releases.
+    pub artists: usize,
+    // Release pages being fetched at once, across every artist.
+    pub releases: usize,
+    // Artists picked up before the crawl ends, counting the ones a previous crawl left unscraped. Unlimited when `None`.
+    pub max_artists: Option<usize>,
+}
+
+impl Default for CrawlLimits {
+    fn default() -> Self {
+        Self {
+            artists: 4,
+            releases: 8,
+            max_artists: None,
+        }
+    }
+}
+
+pub struct BcScraperBuilder {
+    state: ScraperState,
+    base_url: Url,
+    artist_index_path: String,
+    discography_path: String,
+    user_agent: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    rate_limit: RateLimit,
+    default_host_rate_limit: Option<RateLimit>,
+    host_rate_limits: HashMap<String, RateLimit>,
+    retry_policy: RetryPolicy,
+    checkpoint_policy: Option<CheckpointPolicy>,
+    cache_policy: Option<CachePolicy>,
+    warc_policy: Option<WarcPolicy>,
+    classifier: ReleaseClassifier,
+    fetcher: Option<Arc<dyn Fetcher>>,
+}
+
+impl Default for BcScraperBuilder {
+    fn default() -> Self {
+        Self {
+            state: ScraperState::new(),
+            base_url: Url::parse(crate::BANDCAMP_BASE_URL).unwrap(),
+            artist_index_path: crate::BANDCAMP_ARTIST_INDEX_PATH.to_owned(),
+            discography_path: crate::BANDCAMP_DISCOGRAPHY_PATH.to_owned(),
+            user_agent: "bcz/0.1".to_owned(),
+            timeout: Duration::from_secs(2),
+            connect_timeout: Duration::from_millis(700),
+            rate_limit: RateLimit::default(),
+            default_host_rate_limit: None,
+            host_rate_limits: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            checkpoint_policy: None,
+            cache_policy: None,
+            warc_policy: None,
+            classifier: ReleaseClassifier::default(),
+            fetcher: None,
+        }
+    }
+}
+
+impl BcScraperBuilder {
+    pub fn state(mut self, state: ScraperState) -> Self {
+        self.state = state;
+        self
+    }
+
+    // Where the artist index lives, e.g. a mirror, a server replaying recorded responses, or a local server in tests. Only its scheme, host and port are used.
+    pub fn base_url(mut self, base_url: Url) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    // Path of the artist index under the base url.
+    pub fn artist_index_path(mut self, artist_index_path: impl Into<String>) -> Self {
+        self.artist_index_path = artist_index_path.into();
+        self
+    }
+
+    // Path of the discography page under each artist's url. Artists live on their own hosts, so this doesn't go through the base url.
+    pub fn discography_path(mut self, discography_path: impl Into<String>) -> Self {
+        self.discography_path = discography_path.into();
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    // Budget shared by every request the scraper makes, regardless of host. Zero, negative or NaN rates fall back to a very slow minimum.
+    pub fn requests_per_second(mut self, requests_per_second: f64) -> Self {
+        self.rate_limit = RateLimit::new(requests_per_second, self.rate_limit.burst);
+        self
+    }
+
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.rate_limit = RateLimit::new(self.rate_limit.requests_per_second, burst);
+        self
+    }
+
+    // Applied to each host separately, on top of the global budget. Artists live on their own subdomains, so this is what keeps us from hammering any single one of them.
+    pub fn per_host_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.default_host_rate_limit = Some(rate_limit);
+        self
+    }
+
+    // Overrides the per-host limit for a specific host, e.g. `bandcamp.com` for the artist index.
+    pub fn host_rate_limit(mut self, host: impl Into<String>, rate_limit: RateLimit) -> Self {
+        self.host_rate_limits.insert(host.into(), rate_limit);
+        self
+    }
+
+    // Used for every fetch, including the artist index pages.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn checkpoint(mut self, checkpoint_policy: CheckpointPolicy) -> Self {
+        self.checkpoint_policy = Some(checkpoint_policy);
+        self
+    }
+
+    // Keeps pages on disk, so re-running a crawl only asks Bandcamp about pages that went stale, and even then lets it answer with a 304.
+    pub fn cache(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = Some(cache_policy);
+        self
+    }
+
+    // Archives every request and response that goes over the network, so a crawl can be parsed again after fixing a parser.
+    pub fn warc(mut self, warc_policy: WarcPolicy) -> Self {
+        self.warc_policy = Some(warc_policy);
+        self
+    }
+
+    // Decides which releases are singles, EPs or albums.
+    pub fn classifier(mut self, classifier: ReleaseClassifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    // Replaces the default reqwest transport. The user agent and timeouts only apply to the default one.
+    pub fn fetcher(mut self, fetcher: impl Fetcher + 'static) -> Self {
+        self.fetcher = Some(Arc::new(fetcher));
+        self
+    }
+
+    pub fn build(self) -> BcScraper {
+        let fetcher = match self.fetcher {
+            Some(fetcher) => fetcher,
+            None => {
+                let client = Client::builder()
+                    .user_agent(self.user_agent)
+                    .gzip(true)
+                    .timeout(self.timeout)
+                    .connect_timeout(self.connect_timeout)
+                    .build()
+                    .unwrap();
+
+                Arc::new(ReqwestFetcher::new(client))
+            }
+        };
+        let rate_limiter = RateLimiter::new(self.rate_limit, self.default_host_rate_limit, self.host_rate_limits);
+
+        let mut artist_index_url = self.base_url;
+        artist_index_url.set_path(&self.artist_index_path);
+        artist_index_url.set_query(None);
+
+        BcScraper {
+            http: HttpClient::new(fetcher, rate_limiter, self.retry_policy, self.cache_policy.map(HttpCache::new), self.warc_policy.map(WarcWriter::new)),
+            artist_index_url,
+            discography_path: self.discography_path,
+            state: Arc::new(RwLock::new(self.state)),
+            checkpointer: self.checkpoint_policy.map(|policy| Arc::new(Checkpointer::new(policy))),
+            classifier: self.classifier,
+        }
+    }
 }
 
 impl BcScraper {
+    pub fn builder() -> BcScraperBuilder {
+        BcScraperBuilder::default()
+    }
+
     pub fn with_state(state: ScraperState) -> Self {
-        // TODO: also set a rate limiter.
-        let client = Client::builder()
-            .user_agent("bcz/0.1")
-            .gzip(true)
-            .timeout(Duration::from_secs(2))
-            .connect_timeout(Duration::from_millis(700))
-            .build()
-            .unwrap();
+        Self::builder().state(state).build()
+    }
 
-        Self {
-            client,
-            state: Arc::new(RwLock::new(state)),
+    pub fn state(&self) -> RwLockReadGuard<'_, ScraperState> {
+        self.state.read().unwrap()
+    }
+
+    // Writes the state to the checkpoint path right away. Does nothing if checkpointing isn't configured.
+    pub fn checkpoint(&self) -> Result<()> {
+        match &self.checkpointer {
+            Some(checkpointer) => checkpointer.checkpoint(&self.state),
+            None => Ok(()),
         }
     }
 
     pub fn artists(&self) -> Artists {
-        Artists::from(&self)
+        Artists::from(self)
     }
 
-    // What about artists with their own custom domain/page?
-    pub async fn artist_releases(&self, artist_url: &ArtistUrl) -> Result<Releases, Box<dyn Error>> {
-        Releases::for_artist(&artist_url, &self).await
-    }
+    // Artists with their own custom domain are also reachable through a `*.bandcamp.com` subdomain, which is what we want to store them by. This fetches the custom domain page to find it, and fills in the band id if the page has it. Urls already on `*.bandcamp.com` are only normalized.
+    pub async fn resolve_artist_url(&self, artist_url: &ArtistUrl) -> Result<ArtistUrl> {
+        if !artist_url.is_custom_domain() {
+            return Ok(ArtistUrl {
+                url: artist_url.canonical_url(),
+                ..artist_url.clone()
+            });
+        }
 
-    pub async fn discography(&self, artist_url: &ArtistUrl) -> Result<ArtistDiscography, Box<dyn Error>> {
-        let albums = vec![];
-        let eps = vec![];
-        let singles = vec![];
+        let url = Url::parse(&artist_url.canonical_url()).map_err(|e| Error::InvalidUrl { url: artist_url.url.clone(), source: e })?;
+        let page = self.http.get_text(url, PageKind::Artist).await?;
+        let (bandcamp_url, band_id) = parse_custom_domain_page(&page);
+
+        debug!(artist_url = %artist_url.url, ?bandcamp_url, ?band_id, "Resolved the artist's custom domain.");
+
+        if bandcamp_url.is_none() && band_id.is_none() {
+            warn!(artist_url = %artist_url.url, "Couldn't find the bandcamp subdomain or band id behind a custom domain, keeping the url as is.");
+        }
 
-        Ok(ArtistDiscography {
-            albums,
-            eps,
-            singles,
+        Ok(ArtistUrl {
+            name: artist_url.name.clone(),
+            url: bandcamp_url.unwrap_or_else(|| artist_url.canonical_url()),
+            band_id: band_id.or(artist_url.band_id),
         })
     }
 
-    pub async fn release(&self, release_url: &ReleaseUrl) {
+    pub async fn artist_releases(&self, artist_url: &ArtistUrl) -> Result<Releases> {
+        Releases::for_artist(artist_url, self).await
+    }
+
+    pub async fn discography(&self, artist_url: &ArtistUrl) -> Result<ArtistDiscography> {
+        Ok(self.scrape_artist(artist_url, &Semaphore::new(1)).await?.discography)
+    }
+
+    // Walks the artist index and scrapes every artist found in it, starting with the ones a previous crawl found but didn't get to. Artists come out in whatever order they finish, with errors for the ones that couldn't be scraped and for the artist index if it gave up; the state gets updated as each one completes.
+    pub fn crawl(&self, limits: CrawlLimits) -> impl Stream<Item = Result<ArtistInfo>> + '_ {
+        self.crawl_until(limits, future::pending())
+    }
+
+    // Like `crawl`, but once `stop` resolves no new artists are picked up. The artists already being scraped still finish before the stream ends, so nothing that was started gets lost.
+    pub fn crawl_until<'a>(&'a self, limits: CrawlLimits, stop: impl Future<Output = ()> + 'a) -> impl Stream<Item = Result<ArtistInfo>> + 'a {
+        let release_permits = Arc::new(Semaphore::new(limits.releases.max(1)));
+        let unscraped_artists = self.state().unscraped_artists();
+        let stop = stop.shared();
+
+        info!(unscraped_artists = unscraped_artists.len(), ?limits, "Starting the crawl.");
+
+        // Stopping waits for the page of the index that might be in flight, like `Artists::shutdown` does.
+        let artists = stream::unfold(Some((self.artists(), stop.clone())), |state| async move {
+            let (mut artists, stop) = state?;
+
+            tokio::select! {
+                biased;
+                _ = stop.clone() => {
+                    artists.shutdown().await;
+                    None
+                }
+                artist_url = artists.next() => artist_url.map(|artist_url| (artist_url, Some((artists, stop)))),
+            }
+        });
+
+        stream::iter(unscraped_artists.into_iter().map(Ok))
+            .take_until(stop)
+            .chain(artists)
+            .take(limits.max_artists.unwrap_or(usize::MAX))
+            .map(move |artist_url| {
+                let release_permits = release_permits.clone();
+
+                async move {
+                    // The artist index gave up, which it already logged. This is the last item it sends.
+                    let artist_url = artist_url?;
+                    let result = self.scrape_artist(&artist_url, &release_permits).await;
+
+                    if let Err(e) = &result {
+                        warn!(artist_url = %artist_url.url, error = %e, "Failed to scrape the artist. Error: {}", e);
+                    }
+
+                    result
+                }
+            })
+            // Keeps going until every artist it already started is done, even after the artists above ran out.
+            .buffer_unordered(limits.artists.max(1))
+    }
+
+    // Release pages are fetched concurrently, but only while there are `release_permits` left. They're shared between every artist being scraped at the same time. Releases that fail for good are skipped, but any other failure fails the whole artist.
+    async fn scrape_artist(&self, original_url: &ArtistUrl, release_permits: &Semaphore) -> Result<ArtistInfo> {
+        let artist_url = &self.resolve_artist_url(original_url).await?;
+        let release_urls: Vec<_> = self.artist_releases(artist_url).await?.collect();
+        let release_count = release_urls.len();
+
+        let releases: Vec<_> = stream::iter(release_urls)
+            .map(|release_url| async move {
+                let _permit = release_permits.acquire().await.unwrap();
+                debug!(release_url = %release_url.url, "Fetching release for the artist's discography.");
+
+                match self.release(&release_url).await {
+                    Ok(release) => Ok(Some(release)),
+                    // A release that's gone or that we can't parse won't get better by scraping the artist again, so it shouldn't hold back the rest of the discography.
+                    Err(e) if !e.is_retryable() => {
+                        warn!(release_url = %release_url.url, error = %e, "Skipping a release we couldn't scrape. Error: {}", e);
+                        Ok(None)
+                    }
+                    // The artist is left unscraped, so the next crawl tries it again.
+                    Err(e) => Err(e),
+                }
+            })
+            // The permits are what limits the fetches, this only keeps the releases in order.
+            .buffered(release_count.max(1))
+            .try_collect()
+            .await?;
+
+        let mut discography = ArtistDiscography::default();
+
+        for release in releases.into_iter().flatten() {
+            discography.add_release(release);
+        }
+
+        info!(artist_url = %artist_url.url, albums = discography.albums.len(), eps = discography.eps.len(), singles = discography.singles.len(), "Finished scraping the artist's discography.");
+
+        let artist = {
+            let mut state = self.state.write().unwrap();
+            // The index gave us the url we started from, so that's what the artist is stored under until now.
+            let artist_url = state.move_artist(original_url, artist_url);
+            state.update_artist_discography(&artist_url, discography);
+            state.artist(&artist_url).cloned().expect("the artist was just added to the state")
+        };
+
+        if let Some(checkpointer) = &self.checkpointer {
+            checkpointer.artist_updated(&self.state);
+        }
+
+        Ok(artist)
+    }
+
+    pub async fn release(&self, release_url: &ReleaseUrl) -> Result<Release> {
+        let mut release = parse_release_page(&self.http, release_url).await?;
+        self.classifier.apply(&mut release);
+
+        debug!(release_url = %release_url.url, kind = ?release.kind, classified_by = ?release.classified_by, "Classified the release.");
+
+        Ok(release)
     }
 }
\ No newline at end of file
@@ -1,46 +1,188 @@
-use std::{future::Future, pin::Pin, task::{Context, Poll}, sync::{Arc, RwLock}, collections::VecDeque, time::Duration};
+use std::{future::Future, pin::Pin, task::{Context, Poll}, sync::{Arc, RwLock}, collections::VecDeque};
 
-use reqwest::{Client, Response, Error};
+use reqwest::Url;
 use scraper::{Html, Selector};
+use serde::Deserialize;
 use tokio::{task::JoinHandle, time::{sleep, Sleep}};
 use tokio_stream::Stream;
 use tracing::{debug, info, warn, error};
 
-use crate::{bc::BcScraper, ScraperState};
+use crate::{bc::BcScraper, cache::PageKind, checkpoint::Checkpointer, error::{Error, Result}, fetch::FetchResponse, http::HttpClient, ScraperState};
 
 #[derive(Clone, Debug)]
 pub struct ArtistUrl {
     pub name: String,
     pub url: String,
+    // Only known once we've looked at the artist's own page, see `BcScraper::resolve_artist_url`.
+    pub band_id: Option<u64>,
+}
+
+const BANDCAMP_DOMAIN: &str = "bandcamp.com";
+
+// Subdomains that belong to Bandcamp itself rather than to an artist.
+const NON_ARTIST_SUBDOMAINS: &[&str] = &["www", "daily", "blog", "get", "help", "s4", "f4", "t4", "bandcamp"];
+
+pub(crate) fn is_bandcamp_artist_host(host: &str) -> bool {
+    match host.strip_suffix(BANDCAMP_DOMAIN).and_then(|rest| rest.strip_suffix('.')) {
+        Some(subdomain) => !subdomain.is_empty() && !subdomain.contains('.') && !NON_ARTIST_SUBDOMAINS.contains(&subdomain),
+        None => false,
+    }
+}
+
+fn parse_artist_url(url: &str) -> std::result::Result<Url, url::ParseError> {
+    let url = url.trim();
+
+    match Url::parse(url) {
+        Err(url::ParseError::RelativeUrlWithoutBase) => Url::parse(&format!("https://{}", url)),
+        r => r,
+    }
+}
+
+// Turns the different ways the same artist url can be written into a single key. Query strings, fragments, trailing slashes, default ports and host case never matter. Custom domains are served over https too, so they're forced to it as well, unless they're on an ip or an explicit port (e.g. a local mirror), where the scheme might actually matter. On `*.bandcamp.com` the artist is the subdomain, so we also drop the path there, while custom domains keep theirs.
+pub(crate) fn canonical_artist_url(url: &str) -> String {
+    let mut url = match parse_artist_url(url) {
+        Ok(url) if url.has_host() => url,
+        _ => return url.trim().trim_end_matches('/').to_lowercase(),
+    };
+
+    url.set_query(None);
+    url.set_fragment(None);
+
+    if let Some(host) = url.host_str().map(|h| h.trim_end_matches('.').to_owned()) {
+        if is_bandcamp_artist_host(&host) {
+            return format!("https://{}", host);
+        }
+
+        // Setting the same host again is how we get rid of a trailing dot.
+        let _ = url.set_host(Some(&host));
+
+        if matches!(url.host(), Some(url::Host::Domain(_))) && url.port().is_none() {
+            let _ = url.set_scheme("https");
+        }
+    }
+
+    url.as_str().trim_end_matches('/').to_owned()
+}
+
+// Artist pages embed some info about the band in a `data-band` attribute.
+#[derive(Deserialize)]
+struct BandData {
+    id: u64,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+// Looks for the band id and the `*.bandcamp.com` url behind a custom domain page. The url comes from the band data when it has one, and from the page's own releases otherwise. Pages (labels especially) also link to other artists, so any other link to a subdomain says nothing, and releases on more than one subdomain are too ambiguous to pick from.
+pub(crate) fn parse_custom_domain_page(page: &str) -> (Option<String>, Option<u64>) {
+    let document = Html::parse_document(page);
+
+    let band_selector = Selector::parse("[data-band]").unwrap();
+    let band_data = document.select(&band_selector).next()
+        .and_then(|e| e.value().attr("data-band"))
+        .and_then(|band_json| match serde_json::from_str::<BandData>(band_json) {
+            Ok(band_data) => Some(band_data),
+            Err(e) => {
+                warn!(error = %e, "Artist page has band data we couldn't parse! Error: {}", e);
+                None
+            }
+        });
+
+    let is_artist_url = |url: &str| parse_artist_url(url).ok()
+        .and_then(|url| url.host_str().map(is_bandcamp_artist_host))
+        .unwrap_or(false);
+
+    let band_id = band_data.as_ref().map(|b| b.id);
+    let bandcamp_url = band_data.and_then(|b| b.url).filter(|url| is_artist_url(url)).or_else(|| {
+        let link_selector = Selector::parse("a[href]").unwrap();
+
+        let mut release_hosts: Vec<_> = document.select(&link_selector)
+            .filter_map(|e| e.value().attr("href"))
+            .filter(|href| is_artist_url(href))
+            .filter_map(|href| parse_artist_url(href).ok())
+            .filter(|url| url.path().starts_with("/album/") || url.path().starts_with("/track/"))
+            .map(|url| canonical_artist_url(url.as_str()))
+            .collect();
+        release_hosts.sort();
+        release_hosts.dedup();
+
+        match release_hosts.as_slice() {
+            [host] => Some(host.clone()),
+            [] => None,
+            hosts => {
+                warn!(?hosts, "Artist page has releases on different subdomains, so we can't tell which one is the artist's.");
+                None
+            }
+        }
+    });
+
+    (bandcamp_url.map(|url| canonical_artist_url(&url)), band_id)
+}
+
+struct ArtistIndexPage {
+    artists: VecDeque<ArtistUrl>,
+    has_next_page: bool,
 }
 
 impl ArtistUrl {
-    #[tracing::instrument(skip(r), fields(url = %r.url(), status = %r.status()))]
-    async fn parse_from_response(r: Response) -> VecDeque<ArtistUrl> {
+    pub fn canonical_url(&self) -> String {
+        canonical_artist_url(&self.url)
+    }
+
+    pub fn is_custom_domain(&self) -> bool {
+        parse_artist_url(&self.url).ok()
+            .and_then(|url| url.host_str().map(|host| !is_bandcamp_artist_host(host.trim_end_matches('.'))))
+            .unwrap_or(false)
+    }
+
+    #[tracing::instrument(skip(r), fields(url = %r.url, status = %r.status))]
+    fn parse_from_response(r: FetchResponse) -> Result<ArtistIndexPage> {
         let mut res = VecDeque::new();
 
         debug!("Will begin parsing the response and collect all artist urls.");
-        let page_text = r.text().await.unwrap();
+        let page_text = r.text();
         let document = Html::parse_document(&page_text);
         let artist_selector = Selector::parse("li.item").unwrap();
         let url_selector = Selector::parse("a").unwrap();
         let artist_name_selector = Selector::parse("div.itemtext").unwrap();
 
         for element in document.select(&artist_selector) {
-            let url = element.select(&url_selector).next().unwrap();
-            let artist_url = url.value().attr("href").unwrap().to_owned();
-            let name = url.select(&artist_name_selector).next().unwrap();
-            let artist_name = name.text().next().unwrap().to_owned();
-
-            res.push_back(ArtistUrl {
-                name: artist_name,
-                url: artist_url,
+            // A single odd entry shouldn't make us lose the whole page, so we skip it instead.
+            let artist_url = element.select(&url_selector).next().and_then(|url| {
+                let artist_url = url.value().attr("href")?.to_owned();
+                let name = url.select(&artist_name_selector).next()?;
+                let artist_name = name.text().collect::<String>().trim().to_owned();
+
+                Some(ArtistUrl {
+                    name: artist_name,
+                    url: artist_url,
+                    band_id: None,
+                })
             });
+
+            match artist_url {
+                Some(artist_url) => res.push_back(artist_url),
+                None => warn!(element = %element.html(), "Skipping an artist entry without a url or a name."),
+            }
+        }
+
+        // If the page has a pager, the last page won't have a link to the next one. Pages without a pager are treated as having a next page until we get an empty one.
+        let pager_selector = Selector::parse(".pager").unwrap();
+        let next_page_selector = Selector::parse(".pager a.next").unwrap();
+        let has_pager = document.select(&pager_selector).next().is_some();
+
+        // An empty page is how the index ends, but only if it's really the index. Anything else (a bot check, a maintenance page, a new layout) would otherwise end the crawl for good.
+        let item_list_selector = Selector::parse("ul.item_list").unwrap();
+        if res.is_empty() && !has_pager && document.select(&item_list_selector).next().is_none() {
+            return Err(Error::parse(r.url.as_str(), "li.item"));
         }
+        let has_next_page = !res.is_empty() && (!has_pager || document.select(&next_page_selector).next().is_some());
 
-        info!(artist_urls_parsed = res.len(), "Parsed {} artist urls successfully.", res.len());
+        info!(artist_urls_parsed = res.len(), has_next_page, "Parsed {} artist urls successfully.", res.len());
 
-        res
+        Ok(ArtistIndexPage {
+            artists: res,
+            has_next_page,
+        })
     }
 }
 
@@ -48,34 +190,58 @@ impl ArtistUrl {
 enum ArtistsPollState {
     HasArtistsFetched,
     WaitingForPageFetch,
-    WaitingForPageParse,
     WaitingForSleep,
+    Finished,
 }
 
 pub struct Artists {
     poll_state: ArtistsPollState,
-    client: Client,
+    http: HttpClient,
+    artist_index_url: Url,
     state: Arc<RwLock<ScraperState>>,
+    checkpointer: Option<Arc<Checkpointer>>,
     fetched_artists: VecDeque<ArtistUrl>,
     artists_per_page: usize,
-    current_fetch_task: Option<JoinHandle<Result<Response, Error>>>,
+    current_fetch_task: Option<JoinHandle<Result<FetchResponse>>>,
     current_sleep_task: Option<Pin<Box<Sleep>>>,
-    current_page_parse_future: Option<Pin<Box<dyn Future<Output = VecDeque<ArtistUrl>>>>>,
     current_fetched_page_number: usize,
+    reached_last_page: bool,
+    consecutive_failures: u32,
 }
 
 impl Artists {
     pub fn from(scraper: &BcScraper) -> Self {
+        let poll_state = if scraper.state.read().unwrap().artist_index_completed {
+            ArtistsPollState::Finished
+        } else {
+            ArtistsPollState::HasArtistsFetched
+        };
+
         Self {
-            poll_state: ArtistsPollState::HasArtistsFetched,
-            client: scraper.client.clone(),
+            poll_state,
+            http: scraper.http.clone(),
+            artist_index_url: scraper.artist_index_url.clone(),
             state: scraper.state.clone(),
+            checkpointer: scraper.checkpointer.clone(),
             fetched_artists: VecDeque::new(),
             artists_per_page: 0,
             current_fetch_task: None,
             current_sleep_task: None,
-            current_page_parse_future: None,
             current_fetched_page_number: 0,
+            reached_last_page: false,
+            consecutive_failures: 0,
+        }
+    }
+
+    // Stops the stream, waiting for the page fetch that might still be in flight so it doesn't outlive us. Artists that were fetched but not returned yet aren't in the state, so they'll be fetched again when resuming.
+    pub async fn shutdown(mut self) {
+        self.poll_state = ArtistsPollState::Finished;
+
+        if let Some(fetch_task) = self.current_fetch_task.take() {
+            info!("Waiting for the in-flight page fetch before shutting down.");
+            if let Err(e) = fetch_task.await {
+                warn!(error = %e, "The in-flight page fetch failed while shutting down. Error: {}", e);
+            }
         }
     }
 
@@ -85,20 +251,81 @@ impl Artists {
 
     #[tracing::instrument(skip(self))]
     fn next_artist_page(&mut self) -> usize {
-        if self.artists_per_page > 0 {
-            let current_next_artist_number = self.next_artist_number();
-            let remaining_artists_in_queue = self.fetched_artists.len();
+        let current_next_artist_number = self.next_artist_number();
+        let remaining_artists_in_queue = self.fetched_artists.len();
 
-            let next_artist_number_to_fetch = current_next_artist_number + remaining_artists_in_queue;
+        let next_artist_number_to_fetch = current_next_artist_number + remaining_artists_in_queue;
 
-            // Adding 1 because we need to start counting from 0.
-            let next_page = 1 + (next_artist_number_to_fetch / self.artists_per_page);
+        match next_artist_number_to_fetch.checked_div(self.artists_per_page) {
+            Some(page_offset) => {
+                // Adding 1 because we need to start counting from 0.
+                let next_page = 1 + page_offset;
 
-            debug!(self.artists_per_page, current_next_artist_number, remaining_artists_in_queue, next_artist_number_to_fetch, next_page, "Next artist page is {}.", next_page);
+                debug!(self.artists_per_page, current_next_artist_number, remaining_artists_in_queue, next_artist_number_to_fetch, next_page, "Next artist page is {}.", next_page);
 
-            next_page
+                next_page
+            }
+            None => 1,
+        }
+    }
+
+    // The client already retried the request itself, so getting here means the index is having a bad time. We back off and try the page again, but give up after the retry policy's `max_attempts` failures in a row, or right away if retrying can't help. Returns the error when giving up, so it can end the stream.
+    fn handle_page_error(&mut self, e: Error) -> Option<Error> {
+        self.consecutive_failures += 1;
+        let max_attempts = self.http.retry_policy().max_attempts;
+
+        if !e.is_retryable() || self.consecutive_failures >= max_attempts {
+            error!(error = %e, self.consecutive_failures, max_attempts, "Giving up on the artist index, finishing the stream without going through all of it.");
+            self.poll_state = ArtistsPollState::Finished;
+            return Some(e);
+        }
+
+        self.sleep_before_retrying();
+        None
+    }
+
+    fn sleep_before_retrying(&mut self) {
+        let delay = self.http.retry_policy().backoff(self.consecutive_failures);
+        info!(self.consecutive_failures, ?delay, "Will try fetching the page of artists again in {:?}.", delay);
+
+        self.current_sleep_task = Some(Box::pin(sleep(delay)));
+        self.poll_state = ArtistsPollState::WaitingForSleep;
+    }
+
+    // Queues up the artists of a page we just parsed, or fetches another page if it wasn't the one we needed.
+    fn handle_page(&mut self, page: ArtistIndexPage) {
+        debug!(artists_parsed = page.artists.len(), page.has_next_page, "Page parsing has finished!");
+        self.consecutive_failures = 0;
+
+        if self.artists_per_page == 0 {
+            self.artists_per_page = page.artists.len();
+            debug!(self.artists_per_page, "We didn't know how many artists per page we'd get, so we're updating this value now.");
+        }
+
+        if page.artists.is_empty() {
+            debug!(self.current_fetched_page_number, "Got an empty page, so we went past the end of the artist index.");
+            self.reached_last_page = true;
+            self.poll_state = ArtistsPollState::HasArtistsFetched;
+        } else if self.next_artist_page() != self.current_fetched_page_number {
+            // If this was the first page that we fetched just to populate the number of artists per page, and the artist we're looking for isn't in this page, just request a new one.
+            debug!(next_artist_number = self.next_artist_number(), self.current_fetched_page_number, "We fetched a page that didn't have the artist we're looking for, so we'll fetch a different page now.");
+            self.poll_state = ArtistsPollState::WaitingForPageFetch;
+            self.trigger_fetch_next_artist_page();
         } else {
-            1
+            // A page shorter than the others is also the last one, even if the pager says otherwise.
+            if !page.has_next_page || page.artists.len() < self.artists_per_page {
+                debug!(self.current_fetched_page_number, "This is the last page of the artist index.");
+                self.reached_last_page = true;
+            }
+
+            // We'll only get here once we ran out of artists in our current queue, so we can just exchange the queues. When resuming, the page may start with artists we already returned, so we skip those.
+            let first_artist_number_in_page = (self.current_fetched_page_number - 1) * self.artists_per_page;
+            let artists_to_skip = self.next_artist_number().saturating_sub(first_artist_number_in_page).min(page.artists.len());
+            debug!(first_artist_number_in_page, artists_to_skip, "Skipping artists we already returned from this page.");
+
+            self.fetched_artists = page.artists;
+            self.fetched_artists.drain(..artists_to_skip);
+            self.poll_state = ArtistsPollState::HasArtistsFetched;
         }
     }
 
@@ -111,7 +338,8 @@ impl Artists {
 
         info!("Triggering a new page fetch for artists.");
 
-        let client = self.client.clone();
+        let http = self.http.clone();
+        let artist_index_url = self.artist_index_url.clone();
         let page = self.next_artist_page();
 
         info!(self.artists_per_page, page, "Going to fetch page {} for artists.", page);
@@ -119,26 +347,28 @@ impl Artists {
         self.current_fetched_page_number = page;
 
         let fetch_task = tokio::spawn(async move {
-            client.get("https://bandcamp.com/artist_index")
-            .query(&[("sort_asc", "1"), ("page", &page.to_string())])
-            .send()
-            .await
+            let mut url = artist_index_url;
+            url.query_pairs_mut()
+                .append_pair("sort_asc", "1")
+                .append_pair("page", &page.to_string());
+
+            http.get(url, PageKind::ArtistIndex).await
         });
 
         self.current_fetch_task = Some(fetch_task);
     }
 }
 
-// TODO: figure out what to do when we finish going through all pages and get a page with empty results.
+// Ends with an error if the index keeps failing, see `handle_page_error`.
 impl Stream for Artists {
-    type Item = ArtistUrl;
+    type Item = Result<ArtistUrl>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         loop {
             match self.poll_state {
                 ArtistsPollState::HasArtistsFetched => {
                     debug!(self.poll_state = ?self.poll_state, "Has artists fetched.");
-                    if self.fetched_artists.len() <= 5 {
+                    if self.fetched_artists.len() <= 5 && !self.reached_last_page {
                         self.trigger_fetch_next_artist_page();
                     }
 
@@ -150,16 +380,30 @@ impl Stream for Artists {
                             let mut state = self.state.write().unwrap();
                             state.next_artist_number += 1;
                             // Important: we'll also add the artist to the state to make sure we capture the fact we just returned it to the user.
-                            state.new_artist_from_url(artist_url_to_return.clone().unwrap());
+                            if let Err(e) = state.new_artist_from_url(artist_url_to_return.clone().unwrap()) {
+                                warn!(error = %e, "Artist is already in the state, keeping the existing one. Error: {}", e);
+                            }
                             state.next_artist_number
                         };
+
+                        if let Some(checkpointer) = &self.checkpointer {
+                            checkpointer.artist_updated(&self.state);
+                        }
+
                         debug!(next_artist_number, fetched_artists_len = self.fetched_artists.len(), "Still have fetched artists, returning the front one.");
-                        return Poll::Ready(artist_url_to_return);
+                        return Poll::Ready(artist_url_to_return.map(Ok));
+                    } else if self.reached_last_page {
+                        info!(next_artist_number = self.next_artist_number(), "Went through the whole artist index, finishing the stream.");
+                        self.state.write().unwrap().artist_index_completed = true;
+                        self.poll_state = ArtistsPollState::Finished;
                     } else {
                         debug!(fetched_artists_len = self.fetched_artists.len(), "Ran out of fetched artists, will wait for page fetch.");
                         self.poll_state = ArtistsPollState::WaitingForPageFetch;
                     }
                 }
+                ArtistsPollState::Finished => {
+                    return Poll::Ready(None);
+                }
                 ArtistsPollState::WaitingForSleep => {
                     debug!(self.poll_state = ?self.poll_state, "Currently waiting for sleep, will poll the future now.");
                     match self.current_sleep_task.as_mut().unwrap().as_mut().poll(cx) {
@@ -181,50 +425,145 @@ impl Stream for Artists {
                             self.current_fetch_task = None;
 
                             match r {
+                                // A panic while fetching is a bug, so it shouldn't get lost here.
+                                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
                                 Err(e) => {
-                                    panic!("Got a panic while fetching a page of artists! Error: {}", e);
+                                    // The task got cancelled, which says nothing about the page itself, so we just try again.
+                                    error!(self.poll_state = ?self.poll_state, error = %e, "The task fetching a page of artists got cancelled! Error: {}", e);
+                                    self.consecutive_failures += 1;
+                                    self.sleep_before_retrying();
                                 }
                                 Ok(Err(e)) => {
-                                    error!(self.poll_state = ?self.poll_state, error = %e, "We got an error while fetching a page of artists, will try again in 100ms! Error: {}", e);
-
-                                    self.current_sleep_task = Some(Box::pin(sleep(Duration::from_millis(100))));
-                                    self.poll_state = ArtistsPollState::WaitingForSleep;
+                                    error!(self.poll_state = ?self.poll_state, error = %e, "We got an error while fetching a page of artists! Error: {}", e);
+                                    if let Some(e) = self.handle_page_error(e) {
+                                        return Poll::Ready(Some(Err(e)));
+                                    }
                                 }
                                 Ok(Ok(r)) => {
                                     debug!(self.poll_state = ?self.poll_state, "Got a page during fetch. Will now parse it.");
-                                    self.current_page_parse_future = Some(Box::pin(ArtistUrl::parse_from_response(r)));
-                                    self.poll_state = ArtistsPollState::WaitingForPageParse;
+                                    match ArtistUrl::parse_from_response(r) {
+                                        Ok(page) => self.handle_page(page),
+                                        Err(e) => {
+                                            error!(self.poll_state = ?self.poll_state, error = %e, "We got an error while parsing a page of artists! Error: {}", e);
+                                            if let Some(e) = self.handle_page_error(e) {
+                                                return Poll::Ready(Some(Err(e)));
+                                            }
+                                        }
+                                    }
                                 }
                             }
                         }
                     }
                 }
-                ArtistsPollState::WaitingForPageParse => {
-                    debug!(self.poll_state = ?self.poll_state, "Currently waiting for page parsing, will poll the future now.");
-                    match self.current_page_parse_future.as_mut().unwrap().as_mut().poll(cx) {
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(v) => {
-                            debug!(self.poll_state = ?self.poll_state, artists_parsed = v.len(), "Page parsing has finished!");
+            }
+        }
+    }
+}
 
-                            if self.artists_per_page == 0 {
-                                self.artists_per_page = v.len();
-                                debug!(self.artists_per_page, "We didn't know how many artists per page we'd get, so we're updating this value now.");
-                            }
+#[cfg(test)]
+mod tests {
+    use reqwest::{StatusCode, Url, header::HeaderMap};
 
-                            // If this was the first page that we fetched just to populate the number of artists per page, and the artist we're looking for isn't in this page, just request a new one.
-                            if self.next_artist_page() != self.current_fetched_page_number {
-                                debug!(next_artist_number = self.next_artist_number(), self.current_fetched_page_number, "We fetched a page that didn't have the artist we're looking for, so we'll fetch a different page now.");
-                                self.poll_state = ArtistsPollState::WaitingForPageFetch;
-                                self.trigger_fetch_next_artist_page();
-                            } else {
-                                // We'll only get here once we ran out of artists in our current queue, so we can just exchange the queues.
-                                self.fetched_artists = v;
-                                self.poll_state = ArtistsPollState::HasArtistsFetched;
-                            }
-                        }
-                    }
-                }
-            }
+    use super::{canonical_artist_url, parse_custom_domain_page, ArtistUrl};
+    use crate::{error::Error, fetch::FetchResponse};
+
+    fn index_response(page: &str) -> FetchResponse {
+        FetchResponse {
+            url: Url::parse("https://bandcamp.com/artist_index?sort_asc=1&page=1").unwrap(),
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: page.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn parses_artists_from_an_index_page() {
+        let page = ArtistUrl::parse_from_response(index_response(include_str!("../tests/fixtures/artist_index_page.html"))).unwrap();
+
+        let artists: Vec<_> = page.artists.iter().map(|a| (a.name.as_str(), a.url.as_str())).collect();
+        assert_eq!(artists, vec![
+            ("Ghost", "https://ghost.bandcamp.com?from=artist_index"),
+            ("Ghost", "https://ghostband.bandcamp.com?from=artist_index"),
+            ("Sigur Rós", "https://sigur-ros.bandcamp.com?from=artist_index"),
+        ]);
+        assert!(page.has_next_page);
+    }
+
+    #[test]
+    fn last_index_page_skips_broken_entries() {
+        let page = ArtistUrl::parse_from_response(index_response(include_str!("../tests/fixtures/artist_index_last_page.html"))).unwrap();
+
+        let artists: Vec<_> = page.artists.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(artists, vec!["ZZZ"]);
+        assert!(!page.has_next_page);
+    }
+
+    #[test]
+    fn empty_index_page_has_no_next_page() {
+        let page = ArtistUrl::parse_from_response(index_response(include_str!("../tests/fixtures/artist_index_empty.html"))).unwrap();
+
+        assert!(page.artists.is_empty());
+        assert!(!page.has_next_page);
+    }
+
+    #[test]
+    fn unrecognized_pages_are_parse_errors_instead_of_the_end_of_the_index() {
+        let error = ArtistUrl::parse_from_response(index_response(include_str!("../tests/fixtures/artist_index_unrecognized.html"))).err().unwrap();
+
+        assert!(matches!(error, Error::Parse { ref selector, .. } if selector == "li.item"), "got {:?}", error);
+    }
+
+    #[test]
+    fn bandcamp_urls_are_normalized_to_the_subdomain() {
+        for url in ["https://ghost.bandcamp.com", "http://Ghost.Bandcamp.com/", "ghost.bandcamp.com/music?from=artist_index", "https://ghost.bandcamp.com./#top"] {
+            assert_eq!(canonical_artist_url(url), "https://ghost.bandcamp.com", "normalizing {}", url);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn custom_domains_keep_their_path() {
+        assert_eq!(canonical_artist_url("HTTPS://Music.Example.com:443/label/?ref=x"), "https://music.example.com/label");
+        assert_eq!(canonical_artist_url("http://music.example.com/label"), "https://music.example.com/label");
+        assert_eq!(canonical_artist_url("http://music.example.com:80/label"), "https://music.example.com/label");
+        // Local mirrors might not speak https.
+        assert_eq!(canonical_artist_url("http://127.0.0.1:8080/artist/1/"), "http://127.0.0.1:8080/artist/1");
+        assert_eq!(canonical_artist_url("http://127.0.0.1/artist/1"), "http://127.0.0.1/artist/1");
+        assert_eq!(canonical_artist_url("http://localhost:8080/artist/1"), "http://localhost:8080/artist/1");
+    }
+
+    #[test]
+    fn custom_domain_pages_resolve_to_the_subdomain() {
+        let page = r#"<html><body>
+            <div id="pagedata" data-band="{&quot;id&quot;:1234,&quot;name&quot;:&quot;Ghost&quot;}"></div>
+            <a href="https://bandcamp.com/help">help</a>
+            <a href="https://daily.bandcamp.com/">daily</a>
+            <a href="https://ghost.bandcamp.com/album/opus">opus</a>
+        </body></html>"#;
+
+        assert_eq!(parse_custom_domain_page(page), (Some("https://ghost.bandcamp.com".to_owned()), Some(1234)));
+        assert_eq!(parse_custom_domain_page("<html></html>"), (None, None));
+    }
+
+    #[test]
+    fn custom_domain_pages_dont_resolve_to_the_artists_they_link_to() {
+        let label_page = r#"<html><body>
+            <a href="https://ghost.bandcamp.com/">Ghost</a>
+            <link rel="me" href="https://ghost.bandcamp.com">
+            <a href="https://label.bandcamp.com/album/sampler">sampler</a>
+            <a href="https://label.bandcamp.com/track/single">single</a>
+        </body></html>"#;
+        assert_eq!(parse_custom_domain_page(label_page), (Some("https://label.bandcamp.com".to_owned()), None));
+
+        let linking_page = r#"<html><body>
+            <a href="https://ghost.bandcamp.com/">our friends in Ghost</a>
+            <a href="/album/our-own-album">our own album</a>
+        </body></html>"#;
+        assert_eq!(parse_custom_domain_page(linking_page), (None, None));
+
+        let ambiguous_page = r#"<html><body>
+            <a href="https://label.bandcamp.com/album/sampler">sampler</a>
+            <a href="https://ghost.bandcamp.com/album/opus">opus</a>
+        </body></html>"#;
+        assert_eq!(parse_custom_domain_page(ambiguous_page), (None, None));
+    }
+}
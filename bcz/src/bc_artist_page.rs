@@ -1,10 +1,8 @@
-use std::{error::Error, sync::{Arc, RwLock}};
-
-use reqwest::{Url, Client};
+use reqwest::Url;
 use scraper::{Html, Selector};
-use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use crate::{bc_artist_directory::ArtistUrl, bc::BcScraper, ScraperState, RuntimeScraperState};
+use crate::{bc_artist_directory::ArtistUrl, bc::BcScraper, cache::PageKind, error::{Error, Result}, http::HttpClient};
 
 #[derive(Clone, Debug)]
 pub struct ReleaseUrl {
@@ -17,11 +15,9 @@ pub struct Releases {
 }
 
 impl Releases {
-    pub async fn for_artist(artist_url: &ArtistUrl, scraper: &BcScraper) -> Result<Self, Box<dyn Error>> {
-        let client = scraper.client.clone();
-
+    pub async fn for_artist(artist_url: &ArtistUrl, scraper: &BcScraper) -> Result<Self> {
         Ok(Self {
-            fetched_releases: parse_artist_discography_page(client, artist_url).await?,
+            fetched_releases: parse_artist_discography_page(&scraper.http, artist_url, &scraper.discography_path).await?,
         })
     }
 }
@@ -34,12 +30,17 @@ impl Iterator for Releases {
     }
 }
 
-async fn parse_artist_discography_page(client: Client, artist_url: &ArtistUrl) -> Result<Vec<ReleaseUrl>, Box<dyn Error>> {
-    let mut discography_url = Url::parse(&artist_url.url)?;
-    discography_url.set_path(crate::BANDCAMP_DISCOGRAPHY_PATH);
+async fn parse_artist_discography_page(http: &HttpClient, artist_url: &ArtistUrl, discography_path: &str) -> Result<Vec<ReleaseUrl>> {
+    let mut discography_url = Url::parse(&artist_url.url).map_err(|e| Error::InvalidUrl { url: artist_url.url.clone(), source: e })?;
+    discography_url.set_path(discography_path);
+
+    let discography_page = http.get_text(discography_url.clone(), PageKind::Artist).await?;
+
+    parse_discography_document(&discography_url, &discography_page)
+}
 
-    let discography_page = client.get(discography_url).send().await?.text().await.unwrap();
-    let document = Html::parse_document(&discography_page);
+fn parse_discography_document(discography_url: &Url, discography_page: &str) -> Result<Vec<ReleaseUrl>> {
+    let document = Html::parse_document(discography_page);
 
     let album_selector = Selector::parse("li.music-grid-item").unwrap();
     let url_selector = Selector::parse("a").unwrap();
@@ -48,10 +49,19 @@ async fn parse_artist_discography_page(client: Client, artist_url: &ArtistUrl) -
     let mut res = Vec::new();
 
     for element in document.select(&album_selector) {
-        let url = element.select(&url_selector).next().unwrap();
-        let release_url = url.value().attr("href").unwrap().to_owned();
-        let name = url.select(&release_name_selector).next().unwrap();
-        let release_name = name.text().next().unwrap().to_owned();
+        let url = element.select(&url_selector).next().ok_or_else(|| Error::parse(discography_url.as_str(), "li.music-grid-item a"))?;
+        let href = url.value().attr("href").ok_or_else(|| Error::parse(discography_url.as_str(), "li.music-grid-item a[href]"))?;
+        // Releases are usually linked relative to the artist's page.
+        let release_url = match discography_url.join(href) {
+            Ok(release_url) => release_url.to_string(),
+            Err(e) => {
+                warn!(href, error = %e, "Skipping a release with an invalid url.");
+                continue;
+            }
+        };
+        let name = url.select(&release_name_selector).next().ok_or_else(|| Error::parse(discography_url.as_str(), "li.music-grid-item a p"))?;
+        // Releases by other artists also have the artist's name in here, after the release name.
+        let release_name = name.text().map(str::trim).find(|t| !t.is_empty()).unwrap_or_default().to_owned();
 
         res.push(ReleaseUrl {
             name: release_name,
@@ -60,4 +70,35 @@ async fn parse_artist_discography_page(client: Client, artist_url: &ArtistUrl) -
     }
 
     Ok(res)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::parse_discography_document;
+    use crate::error::Error;
+
+    fn discography_url() -> Url {
+        Url::parse("https://ghost.bandcamp.com/music").unwrap()
+    }
+
+    #[test]
+    fn parses_releases_from_the_music_page() {
+        let releases = parse_discography_document(&discography_url(), include_str!("../tests/fixtures/discography.html")).unwrap();
+
+        let releases: Vec<_> = releases.iter().map(|r| (r.name.as_str(), r.url.as_str())).collect();
+        assert_eq!(releases, vec![
+            ("Opus Eponymous", "https://ghost.bandcamp.com/album/opus-eponymous"),
+            ("Secular Haze", "https://ghost.bandcamp.com/track/secular-haze"),
+            ("Meliora", "https://ghost.bandcamp.com/album/meliora?from=discog"),
+        ]);
+    }
+
+    #[test]
+    fn release_without_a_link_is_a_parse_error() {
+        let error = parse_discography_document(&discography_url(), include_str!("../tests/fixtures/discography_missing_link.html")).unwrap_err();
+
+        assert!(matches!(error, Error::Parse { ref selector, .. } if selector == "li.music-grid-item a"), "got {:?}", error);
+    }
+}
@@ -1,66 +1,550 @@
-use std::error::Error;
+use std::time::Duration;
 
-use reqwest::Client;
-use scraper::{Html, Selector};
-use tracing::debug;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use tracing::{debug, warn};
 
-use crate::{bc_artist_page::ReleaseUrl, Release, Track};
+use crate::{bc_artist_page::ReleaseUrl, cache::PageKind, error::{Error, Result}, http::HttpClient, parse_duration, PhysicalFormat, Pricing, Release, ReleaseFormat, ReleaseKind, Track};
 
-async fn parse_release_page(client: Client, release_url: &ReleaseUrl) -> Result<Release, Box<dyn Error>> {
-    let discography_page = client.get(&release_url.url).send().await?.text().await.unwrap();
-    let document = Html::parse_document(&discography_page);
+// Release pages embed the data used by the player in a `data-tralbum` attribute. It's a lot more reliable than the markup, so we only look at the HTML when it's missing.
+#[derive(Debug, Deserialize)]
+struct TralbumData {
+    artist: String,
+    item_type: String,
+    id: Option<u64>,
+    art_id: Option<u64>,
+    album_release_date: Option<String>,
+    #[serde(default)]
+    is_preorder: bool,
+    // Also set on the track pages of an album that isn't out yet.
+    #[serde(default)]
+    album_is_preorder: bool,
+    // Only there for free downloads.
+    #[serde(rename = "freeDownloadPage")]
+    free_download_page: Option<String>,
+    current: TralbumCurrent,
+    #[serde(default)]
+    trackinfo: Vec<TralbumTrack>,
+}
 
-    let release_name_selector = Selector::parse("h2.trackTitle").unwrap();
-    let artist_name_selector = Selector::parse("#name-section > h3:nth-child(2) > span:nth-child(1) > a:nth-child(1)").unwrap();
+#[derive(Debug, Deserialize)]
+struct TralbumCurrent {
+    title: String,
+    release_date: Option<String>,
+    minimum_price: Option<f64>,
+}
 
-    let release_name = document.select(&release_name_selector).next().unwrap().text().next().unwrap().to_owned();
-    let artist_name = document.select(&artist_name_selector).next().unwrap().text().next().unwrap().to_owned();
+#[derive(Debug, Deserialize)]
+struct TralbumTrack {
+    track_id: Option<u64>,
+    track_num: Option<usize>,
+    title: String,
+    // Tracks that can't be streamed come with a null duration.
+    duration: Option<f64>,
+}
 
-    let release_purchase_methods_selector = Selector::parse("li.buyItem").unwrap();
-    let purchase_method_selector = Selector::parse(".buyItemPackageTitle").unwrap();
+pub(crate) async fn parse_release_page(http: &HttpClient, release_url: &ReleaseUrl) -> Result<Release> {
+    let url = Url::parse(&release_url.url).map_err(|e| Error::InvalidUrl { url: release_url.url.clone(), source: e })?;
+    let release_page = http.get_text(url, PageKind::Release).await?;
 
-    let single_duration_selector = Selector::parse(".time_total").unwrap();
+    parse_release_document(release_url, &release_page)
+}
 
-    let mut release: Release;
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<String>().trim().to_owned()
+}
 
-    for method in document.select(&release_purchase_methods_selector) {
-        let method_type = method.select(&purchase_method_selector).next().unwrap().text().next().unwrap();
+fn parse_tralbum_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(date, "%d %b %Y %H:%M:%S GMT").ok().map(|date| Utc.from_utc_datetime(&date))
+}
 
-        match method_type {
-            "Digital Track" => {
-                // For sure a single.
-                let duration = document.select(&single_duration_selector).next().unwrap().text().next().unwrap();
+fn format_from_purchase_title(title: &str) -> Option<ReleaseFormat> {
+    let lowercase_title = title.to_lowercase();
 
-                release = Release::Single {
-                    name: release_name.clone(),
-                    tracks: vec![
-                        Track {
-                            index: 1,
-                            name: release_name.clone(),
-                            duration: duration.to_owned(),
-                        },
-                    ],
-                };
-            }
-            "Digital Album" => {
-                // TODO: check if single track in the album, and if so figure out whether to consider this a single or album. A single might also have more than one track, so wtf do I do.
-                // https://support.tunecore.com/hc/en-us/articles/115006689928-What-is-the-difference-between-a-Single-an-EP-and-an-Album-
-                // https://support.symdistro.com/hc/en-us/articles/215985603-What-is-the-difference-between-Single-EP-and-Album-
-            }
-            "CD" => {
+    match title {
+        "Digital Album" | "Digital Track" => Some(ReleaseFormat::Digital),
+        t if t.contains("CD") || lowercase_title.contains("compact disc") => Some(ReleaseFormat::CompactDisc),
+        _ if lowercase_title.contains("cassette") => Some(ReleaseFormat::Cassette),
+        _ if lowercase_title.contains("vinyl") => Some(ReleaseFormat::Vinyl),
+        "" => None,
+        t => Some(ReleaseFormat::Other(t.to_owned())),
+    }
+}
 
-            }
-            "Cassette Tape" => {
+fn parse_physical_format(item: ElementRef) -> Option<PhysicalFormat> {
+    let title_selector = Selector::parse(".buyItemPackageTitle").unwrap();
+    let merch_type_selector = Selector::parse(".merchtype").unwrap();
+    let price_selector = Selector::parse(".buyItemPrice .base-text-color").unwrap();
+    let currency_selector = Selector::parse(".buyItemPrice .buyItemExtra").unwrap();
+    let notable_selector = Selector::parse(".notable").unwrap();
 
-            }
-            t if t.contains("Vinyl") || t.contains("vinyl") => {
+    let description = item.select(&title_selector).next().map(element_text)?;
+    // The merch type is the more reliable of the two, but older pages only have the title.
+    let format = item.select(&merch_type_selector).next()
+        .and_then(|t| format_from_purchase_title(&element_text(t)))
+        .or_else(|| format_from_purchase_title(&description))?;
 
+    if format == ReleaseFormat::Digital {
+        return None;
+    }
+
+    let price = item.select(&price_selector).next().and_then(|p| parse_price(&element_text(p)));
+    let currency = item.select(&currency_selector).next().map(element_text).filter(|c| !c.is_empty());
+    // Shows up as "Sold Out" or "only 3 remaining".
+    let notable = item.select(&notable_selector).map(element_text).collect::<Vec<_>>().join(" ").to_lowercase();
+    let sold_out = notable.contains("sold out");
+    let remaining = notable.find("remaining")
+        .and_then(|i| notable[..i].split_whitespace().last())
+        .and_then(|n| n.parse().ok());
+
+    Some(PhysicalFormat {
+        format,
+        description,
+        price,
+        currency,
+        sold_out,
+        remaining,
+        ship_date: parse_ship_date(&element_text(item)),
+    })
+}
+
+// Prices come with the currency symbol, e.g. "€12" or "$1,025.99".
+fn parse_price(price: &str) -> Option<f64> {
+    let amount: String = price.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect();
+    amount.parse().ok()
+}
+
+// Looks for a date like "March 11, 2022" after "ships" or "releases", which is how both shipping dates and preorder release dates are shown.
+fn parse_ship_date(text: &str) -> Option<NaiveDate> {
+    // Lowercasing can change byte lengths, so the offset is only valid in the lowercased text. Month names parse regardless of case.
+    let lowercase_text = text.to_lowercase();
+    let start = ["ships", "releases"].iter().filter_map(|keyword| lowercase_text.find(keyword)).min()?;
+    let words: Vec<_> = lowercase_text[start..].split_whitespace().collect();
+
+    words.windows(3).find_map(|w| NaiveDate::parse_from_str(&w.join(" "), "%B %d, %Y").ok())
+}
+
+// Reads the digital buy item, which shows e.g. "$7 USD or more", "name your price" or a "Free Download" button.
+fn parse_digital_pricing(document: &Html) -> Option<Pricing> {
+    let digital_item_selector = Selector::parse("li.buyItem.digital").unwrap();
+    let price_selector = Selector::parse(".buyItemPrice .base-text-color").unwrap();
+    let currency_selector = Selector::parse(".buyItemPrice .buyItemExtra").unwrap();
+    let name_your_price_selector = Selector::parse(".buyItemNyp").unwrap();
+
+    let item = document.select(&digital_item_selector).next()?;
+    let text = element_text(item).to_lowercase();
+    let name_your_price = item.select(&name_your_price_selector).next().is_some() || text.contains("name your price");
+    let free = text.contains("free download");
+    // Without a price, a name your price release starts at nothing.
+    let minimum_price = item.select(&price_selector).next()
+        .and_then(|p| parse_price(&element_text(p)))
+        .or_else(|| (name_your_price || free).then_some(0.0));
+
+    Some(Pricing {
+        minimum_price,
+        currency: item.select(&currency_selector).next().map(element_text).filter(|c| !c.is_empty()),
+        name_your_price,
+        free,
+        preorder: text.contains("pre-order"),
+    })
+}
+
+// The parts of a release that only live in the markup, whether or not the page has TralbumData.
+struct PageMetadata {
+    label: Option<String>,
+    tags: Vec<String>,
+    cover_art_url: Option<String>,
+    purchase_titles: Vec<String>,
+    physical_formats: Vec<PhysicalFormat>,
+    pricing: Option<Pricing>,
+}
+
+impl PageMetadata {
+    fn parse(document: &Html) -> Self {
+        let label_selector = Selector::parse("span.back-to-label-name").unwrap();
+        let tag_selector = Selector::parse(".tralbum-tags a.tag").unwrap();
+        let cover_art_selector = Selector::parse(r#"meta[property="og:image"]"#).unwrap();
+        let purchase_title_selector = Selector::parse("li.buyItem .buyItemPackageTitle").unwrap();
+        let buy_item_selector = Selector::parse("li.buyItem").unwrap();
+
+        Self {
+            label: document.select(&label_selector).next().map(element_text).filter(|l| !l.is_empty()),
+            tags: document.select(&tag_selector).map(element_text).filter(|t| !t.is_empty()).collect(),
+            cover_art_url: document.select(&cover_art_selector).next().and_then(|m| m.value().attr("content")).map(str::to_owned),
+            purchase_titles: document.select(&purchase_title_selector).map(element_text).collect(),
+            physical_formats: document.select(&buy_item_selector).filter_map(parse_physical_format).collect(),
+            pricing: parse_digital_pricing(document),
+        }
+    }
+
+    fn formats(&self) -> Vec<ReleaseFormat> {
+        let mut formats = Vec::new();
+
+        for title in &self.purchase_titles {
+            match format_from_purchase_title(title) {
+                Some(format) if !formats.contains(&format) => formats.push(format),
+                _ => {}
             }
-            t => {
-                debug!("Skipping unwanted purchase method \"{}\".", t);
-            }
         }
+
+        formats
+    }
+}
+
+fn parse_release_document(release_url: &ReleaseUrl, release_page: &str) -> Result<Release> {
+    let document = Html::parse_document(release_page);
+    let metadata = PageMetadata::parse(&document);
+
+    match parse_tralbum_data(&document) {
+        Some(tralbum_data) => release_from_tralbum_data(release_url, tralbum_data, metadata),
+        None => {
+            debug!(release_url = %release_url.url, "Release page has no usable TralbumData, falling back to the HTML.");
+            parse_release_html(release_url, &document, metadata)
+        }
+    }
+}
+
+fn parse_tralbum_data(document: &Html) -> Option<TralbumData> {
+    let tralbum_selector = Selector::parse("[data-tralbum]").unwrap();
+    let tralbum_json = document.select(&tralbum_selector).next()?.value().attr("data-tralbum")?;
+
+    match serde_json::from_str(tralbum_json) {
+        Ok(tralbum_data) => Some(tralbum_data),
+        Err(e) => {
+            warn!(error = %e, "Release page has a TralbumData blob we couldn't parse! Error: {}", e);
+            None
+        }
+    }
+}
+
+fn release_from_tralbum_data(release_url: &ReleaseUrl, tralbum_data: TralbumData, metadata: PageMetadata) -> Result<Release> {
+    debug!(release_name = %tralbum_data.current.title, artist_name = %tralbum_data.artist, "Parsing release from TralbumData.");
+
+    if tralbum_data.trackinfo.is_empty() {
+        return Err(Error::parse(&release_url.url, "[data-tralbum] trackinfo"));
+    }
+
+    // Bandcamp only has tracks and albums. Telling singles and EPs apart is up to `ReleaseClassifier`.
+    let kind = match tralbum_data.item_type.as_str() {
+        "track" => ReleaseKind::Single,
+        _ => ReleaseKind::Album,
+    };
+
+    // TralbumData has the exact price and flags, but the currency and whether it's name your price only show up in the markup. Releases that are only sold physically have neither a price nor a digital buy item.
+    let sold_digitally = metadata.pricing.is_some() || tralbum_data.current.minimum_price.is_some() || tralbum_data.free_download_page.is_some();
+    let pricing = sold_digitally.then(|| {
+        let html_pricing = metadata.pricing.clone().unwrap_or_default();
+        Pricing {
+            minimum_price: tralbum_data.current.minimum_price.or(html_pricing.minimum_price),
+            free: tralbum_data.free_download_page.is_some() || html_pricing.free,
+            preorder: tralbum_data.is_preorder || tralbum_data.album_is_preorder,
+            ..html_pricing
+        }
+    });
+    let track_pricing = pricing.clone().filter(|_| kind == ReleaseKind::Single);
+
+    let tracks = tralbum_data.trackinfo.into_iter().enumerate().map(|(i, track)| {
+        Track {
+            id: track.track_id,
+            // Track pages don't have a track number.
+            index: track.track_num.unwrap_or(i + 1),
+            name: track.title,
+            duration: track.duration.and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()),
+            pricing: track_pricing.clone(),
+        }
+    }).collect();
+    let release_date = tralbum_data.album_release_date.as_deref()
+        .or(tralbum_data.current.release_date.as_deref())
+        .and_then(parse_tralbum_date);
+    let cover_art_url = metadata.cover_art_url.clone()
+        .or_else(|| tralbum_data.art_id.map(|art_id| format!("https://f4.bcbits.com/img/a{:010}_10.jpg", art_id)));
+
+    Ok(Release {
+        kind,
+        name: tralbum_data.current.title,
+        artist: tralbum_data.artist,
+        url: release_url.url.clone(),
+        album_id: tralbum_data.id,
+        release_date,
+        formats: metadata.formats(),
+        physical_formats: metadata.physical_formats,
+        pricing,
+        label: metadata.label,
+        tags: metadata.tags,
+        cover_art_url,
+        tracks,
+        classified_by: None,
+    })
+}
+
+fn parse_release_html(release_url: &ReleaseUrl, document: &Html, metadata: PageMetadata) -> Result<Release> {
+    const RELEASE_NAME_SELECTOR: &str = "h2.trackTitle";
+    const ARTIST_NAME_SELECTOR: &str = "#name-section > h3:nth-child(2) > span:nth-child(1) > a:nth-child(1)";
+
+    let release_name_selector = Selector::parse(RELEASE_NAME_SELECTOR).unwrap();
+    let artist_name_selector = Selector::parse(ARTIST_NAME_SELECTOR).unwrap();
+
+    let release_name = document.select(&release_name_selector).next().map(element_text).ok_or_else(|| Error::parse(&release_url.url, RELEASE_NAME_SELECTOR))?;
+    let artist_name = document.select(&artist_name_selector).next().map(element_text).ok_or_else(|| Error::parse(&release_url.url, ARTIST_NAME_SELECTOR))?;
+
+    debug!(%release_name, %artist_name, "Parsing release page.");
+
+    // Track pages live under `/track/`, but we also look at the purchase methods in case the url doesn't tell us.
+    let is_track_page = release_url.url.contains("/track/") || metadata.purchase_titles.iter().any(|t| t == "Digital Track");
+
+    let release_date_selector = Selector::parse(r#"meta[itemprop="datePublished"]"#).unwrap();
+    // Shows up as "20101018".
+    let release_date = document.select(&release_date_selector).next()
+        .and_then(|m| m.value().attr("content"))
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| Utc.from_utc_datetime(&date));
+
+    let release = |kind, tracks| Release {
+        kind,
+        name: release_name.clone(),
+        artist: artist_name.clone(),
+        url: release_url.url.clone(),
+        album_id: None,
+        release_date,
+        formats: metadata.formats(),
+        physical_formats: metadata.physical_formats.clone(),
+        pricing: metadata.pricing.clone(),
+        label: metadata.label.clone(),
+        tags: metadata.tags.clone(),
+        cover_art_url: metadata.cover_art_url.clone(),
+        tracks,
+        classified_by: None,
+    };
+
+    if is_track_page {
+        let single_duration_selector = Selector::parse(".time_total").unwrap();
+        let duration = document.select(&single_duration_selector).next().map(element_text).ok_or_else(|| Error::parse(&release_url.url, ".time_total"))?;
+        let duration = parse_duration(&duration);
+
+        return Ok(release(ReleaseKind::Single, vec![
+            Track {
+                id: None,
+                index: 1,
+                name: release_name.clone(),
+                duration,
+                pricing: metadata.pricing.clone(),
+            },
+        ]));
+    }
+
+    let track_row_selector = Selector::parse("#track_table tr.track_row_view").unwrap();
+    let track_number_selector = Selector::parse(".track_number").unwrap();
+    let track_title_selector = Selector::parse(".track-title").unwrap();
+    let track_duration_selector = Selector::parse(".time").unwrap();
+
+    let mut tracks = Vec::new();
+
+    for (i, row) in document.select(&track_row_selector).enumerate() {
+        // Track numbers show up as "1.", but fall back to the row position if they're missing.
+        let index = row.select(&track_number_selector).next()
+            .and_then(|n| element_text(n).trim_end_matches('.').parse().ok())
+            .unwrap_or(i + 1);
+        let name = row.select(&track_title_selector).next().map(element_text).ok_or_else(|| Error::parse(&release_url.url, "#track_table tr.track_row_view .track-title"))?;
+        // Tracks that can't be streamed don't show a duration.
+        let duration = row.select(&track_duration_selector).next().and_then(|d| parse_duration(&element_text(d)));
+
+        tracks.push(Track {
+            id: None,
+            index,
+            name,
+            duration,
+            pricing: None,
+        });
+    }
+
+    if tracks.is_empty() {
+        return Err(Error::parse(&release_url.url, "#track_table tr.track_row_view"));
+    }
+
+    Ok(release(ReleaseKind::Album, tracks))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::{parse_release_document, parse_ship_date};
+    use crate::{bc_artist_page::ReleaseUrl, error::Error, PhysicalFormat, Pricing, Release, ReleaseFormat, ReleaseKind};
+
+    fn release_url(url: &str) -> ReleaseUrl {
+        ReleaseUrl {
+            name: String::new(),
+            url: url.to_owned(),
+        }
+    }
+
+    // Durations as whole seconds, since TralbumData has fractions the HTML doesn't.
+    fn tracks(release: &Release) -> Vec<(Option<u64>, usize, &str, Option<u64>)> {
+        release.tracks.iter().map(|t| (t.id, t.index, t.name.as_str(), t.duration.map(|d| d.as_secs_f64().round() as u64))).collect()
+    }
+
+    #[test]
+    fn parses_album_from_tralbum_data() {
+        let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/opus-eponymous"), include_str!("../tests/fixtures/album.html")).unwrap();
+
+        assert_eq!(release.kind, ReleaseKind::Album);
+        assert_eq!(release.name, "Opus Eponymous");
+        assert_eq!(release.artist, "Ghost");
+        assert_eq!(release.url, "https://ghost.bandcamp.com/album/opus-eponymous");
+        assert_eq!(release.album_id, Some(1111));
+        assert_eq!(release.release_date, Some(Utc.ymd(2010, 10, 18).and_hms(0, 0, 0)));
+        assert_eq!(release.label.as_deref(), Some("Rise Above Records"));
+        assert_eq!(release.tags, vec!["metal", "doom", "Linköping"]);
+        assert_eq!(release.cover_art_url.as_deref(), Some("https://f4.bcbits.com/img/a0000004242_5.jpg"));
+        assert_eq!(release.formats, vec![ReleaseFormat::Digital, ReleaseFormat::CompactDisc, ReleaseFormat::Cassette, ReleaseFormat::Vinyl]);
+        let tracks = tracks(&release);
+        assert_eq!(tracks.len(), 9);
+        assert_eq!(tracks[0], (Some(101), 1, "Deus Culpa", Some(94)));
+        assert_eq!(tracks[2], (Some(103), 3, "Ritual", Some(269)));
+        assert_eq!(tracks[8], (Some(109), 9, "Genesis", Some(248)));
+        assert_eq!(release.runtime().as_secs(), 2093);
+    }
+
+    #[test]
+    fn parses_physical_formats() {
+        let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/opus-eponymous"), include_str!("../tests/fixtures/album.html")).unwrap();
+
+        assert_eq!(release.physical_formats, vec![
+            PhysicalFormat {
+                format: ReleaseFormat::CompactDisc,
+                description: "Compact Disc (CD)".to_owned(),
+                price: Some(12.0),
+                currency: Some("EUR".to_owned()),
+                sold_out: true,
+                remaining: None,
+                ship_date: None,
+            },
+            PhysicalFormat {
+                format: ReleaseFormat::Cassette,
+                description: "Cassette Tape".to_owned(),
+                price: Some(8.0),
+                currency: Some("EUR".to_owned()),
+                sold_out: false,
+                remaining: Some(3),
+                ship_date: Some(NaiveDate::from_ymd(2010, 11, 1)),
+            },
+            PhysicalFormat {
+                format: ReleaseFormat::Vinyl,
+                description: "Black 12\" Vinyl LP".to_owned(),
+                price: Some(25.99),
+                currency: Some("USD".to_owned()),
+                sold_out: false,
+                remaining: None,
+                ship_date: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn parses_ship_dates_around_text_that_changes_length_when_lowercased() {
+        // The Kelvin sign lowercases to a one byte 'k', which used to shift the keyword's offset into the middle of a character.
+        assert_eq!(parse_ship_date("\u{212A}ULT EDITION ships out on or around March 11, 2022"), Some(NaiveDate::from_ymd(2022, 3, 11)));
+        assert_eq!(parse_ship_date("\u{212A}\u{212A}\u{212A} Releases MARCH 11, 2022"), Some(NaiveDate::from_ymd(2022, 3, 11)));
+        assert_eq!(parse_ship_date("\u{212A} sold out"), None);
+    }
+
+    #[test]
+    fn parses_album_from_html_without_tralbum_data() {
+        let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/opus-eponymous"), include_str!("../tests/fixtures/album_without_tralbum.html")).unwrap();
+
+        assert_eq!(release.kind, ReleaseKind::Album);
+        assert_eq!(release.name, "Opus Eponymous");
+        assert_eq!(release.artist, "Ghost");
+        assert_eq!(release.album_id, None);
+        assert_eq!(release.release_date, Some(Utc.ymd(2010, 10, 18).and_hms(0, 0, 0)));
+        let tracks = tracks(&release);
+        assert_eq!(tracks.len(), 9);
+        assert_eq!(tracks[0], (None, 1, "Deus Culpa", Some(94)));
+        assert_eq!(tracks[8], (None, 9, "Genesis", Some(248)));
+    }
+
+    #[test]
+    fn parses_track_pages_as_singles() {
+        for fixture in [include_str!("../tests/fixtures/track.html"), include_str!("../tests/fixtures/track_without_tralbum.html")] {
+            let release = parse_release_document(&release_url("https://ghost.bandcamp.com/track/secular-haze"), fixture).unwrap();
+
+            assert_eq!((release.kind, release.name.as_str()), (ReleaseKind::Single, "Secular Haze"));
+            assert_eq!(release.formats, vec![ReleaseFormat::Digital]);
+            let tracks = tracks(&release);
+            assert_eq!(tracks.len(), 1);
+            assert_eq!((tracks[0].1, tracks[0].2, tracks[0].3), (1, "Secular Haze", Some(297)));
+
+            let free = Pricing {
+                minimum_price: Some(0.0),
+                currency: None,
+                name_your_price: true,
+                free: true,
+                preorder: false,
+            };
+            assert_eq!(release.pricing.as_ref(), Some(&free));
+            assert_eq!(release.tracks[0].pricing.as_ref(), Some(&free));
+        }
+    }
+
+    #[test]
+    fn parses_digital_pricing() {
+        let name_your_price = Pricing {
+            minimum_price: Some(7.0),
+            currency: Some("USD".to_owned()),
+            name_your_price: true,
+            free: false,
+            preorder: false,
+        };
+
+        for fixture in [include_str!("../tests/fixtures/album.html"), include_str!("../tests/fixtures/album_without_tralbum.html")] {
+            let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/opus-eponymous"), fixture).unwrap();
+
+            assert_eq!(release.pricing.as_ref(), Some(&name_your_price));
+            assert!(release.tracks.iter().all(|t| t.pricing.is_none()));
+        }
+
+        let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/impera"), include_str!("../tests/fixtures/album_preorder.html")).unwrap();
+        assert_eq!(release.pricing, Some(Pricing {
+            minimum_price: Some(10.0),
+            currency: Some("EUR".to_owned()),
+            name_your_price: false,
+            free: false,
+            preorder: true,
+        }));
+    }
+
+    #[test]
+    fn physical_only_releases_have_no_pricing() {
+        for fixture in [include_str!("../tests/fixtures/album_physical_only.html"), include_str!("../tests/fixtures/album_physical_only_without_tralbum.html")] {
+            let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/seven-inches-of-satanic-panic"), fixture).unwrap();
+
+            assert_eq!(release.name, "Seven Inches of Satanic Panic");
+            assert_eq!(release.pricing, None);
+            assert!(release.tracks.iter().all(|t| t.pricing.is_none()));
+            assert_eq!(release.physical_formats.len(), 1);
+            assert_eq!(release.physical_formats[0].format, ReleaseFormat::Vinyl);
+            assert_eq!(release.physical_formats[0].price, Some(9.0));
+        }
+    }
+
+    #[test]
+    fn preorder_tracks_without_streams_have_no_duration() {
+        let release = parse_release_document(&release_url("https://ghost.bandcamp.com/album/impera"), include_str!("../tests/fixtures/album_preorder.html")).unwrap();
+
+        assert_eq!(tracks(&release), vec![
+            (Some(301), 1, "Spillways", Some(197)),
+            (Some(302), 2, "Call Me Little Sunshine", None),
+            (Some(303), 3, "Hunter's Moon", None),
+        ]);
     }
 
-    Ok(release)
-}
\ No newline at end of file
+    #[test]
+    fn missing_elements_are_parse_errors() {
+        let error = parse_release_document(&release_url("https://ghost.bandcamp.com/album/opus-eponymous"), include_str!("../tests/fixtures/release_missing_elements.html")).unwrap_err();
+
+        assert!(matches!(error, Error::Parse { ref selector, .. } if selector.starts_with("#name-section")), "got {:?}", error);
+    }
+}
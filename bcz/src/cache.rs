@@ -0,0 +1,283 @@
+use std::{path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, Url, header::{ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED}};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, warn};
+
+use crate::{error::{Error, Result}, fetch::FetchResponse};
+
+// What a page is, which decides how long we trust a cached copy of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PageKind {
+    ArtistIndex,
+    // An artist's own pages: the custom domain root and the discography.
+    Artist,
+    Release,
+}
+
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    pub dir: PathBuf,
+    // The index keeps growing as artists sign up, so it goes stale quickly.
+    pub artist_index_ttl: Duration,
+    pub artist_ttl: Duration,
+    // Releases rarely change once they're out.
+    pub release_ttl: Duration,
+}
+
+impl CachePolicy {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            artist_index_ttl: Duration::from_secs(60 * 60),
+            artist_ttl: Duration::from_secs(24 * 60 * 60),
+            release_ttl: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+
+    fn ttl(&self, kind: PageKind) -> Duration {
+        match kind {
+            PageKind::ArtistIndex => self.artist_index_ttl,
+            PageKind::Artist => self.artist_ttl,
+            PageKind::Release => self.release_ttl,
+        }
+    }
+}
+
+// Stored next to the body, which is kept as is in its own file.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    // The url we asked for, which is what the entry is keyed by.
+    request_url: String,
+    // The url the response came from, after any redirects.
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    stored_at: DateTime<Utc>,
+    // Lets us tell when the body on disk isn't the one this entry was written with, e.g. after a crash while replacing it. Missing on entries from before it was added.
+    #[serde(default)]
+    body_hash: Option<u64>,
+}
+
+pub(crate) struct CachedResponse {
+    entry: CacheEntry,
+    pub(crate) response: FetchResponse,
+}
+
+impl CachedResponse {
+    pub(crate) fn age(&self) -> Duration {
+        (Utc::now() - self.entry.stored_at).to_std().unwrap_or_default()
+    }
+
+    // Headers that let the server answer with a 304 if the page didn't change since we cached it.
+    pub(crate) fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Some(etag) = self.response.headers.get(ETAG) {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = self.response.headers.get(LAST_MODIFIED) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+
+        headers
+    }
+}
+
+// Keeps successful responses on disk, keyed by url. Every entry is two files named after a hash of the url: `<hash>.json` with the metadata and `<hash>.body` with the body.
+pub(crate) struct HttpCache {
+    policy: CachePolicy,
+}
+
+impl HttpCache {
+    pub(crate) fn new(policy: CachePolicy) -> Self {
+        Self {
+            policy,
+        }
+    }
+
+    pub(crate) fn is_fresh(&self, cached: &CachedResponse, kind: PageKind) -> bool {
+        cached.age() < self.policy.ttl(kind)
+    }
+
+    // A broken entry is only a cache miss, so this logs errors instead of returning them.
+    pub(crate) async fn lookup(&self, url: &Url) -> Option<CachedResponse> {
+        match self.read_entry(url).await {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!(%url, error = %e, "Ignoring a broken cache entry. Error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn store(&self, request_url: &Url, response: &FetchResponse) -> Result<()> {
+        let entry = CacheEntry {
+            request_url: request_url.to_string(),
+            url: response.url.to_string(),
+            status: response.status.as_u16(),
+            headers: headers_to_pairs(&response.headers),
+            stored_at: Utc::now(),
+            body_hash: Some(fnv1a(&response.body)),
+        };
+
+        fs::create_dir_all(&self.policy.dir).await.map_err(|source| Error::Io { path: self.policy.dir.clone(), source })?;
+
+        // Both files are replaced with a rename, so readers never see half of one. They can still see the new body with the old metadata (or the other way around) in between the two renames, which the body hash catches.
+        self.write_file(request_url, "body", &response.body).await?;
+        self.write_entry(request_url, &entry).await?;
+
+        debug!(url = %request_url, "Cached the response.");
+        Ok(())
+    }
+
+    // Called after a 304: keeps the body we have, but takes any new headers and restarts the ttl. `cached` gets the new headers even if writing them down fails.
+    pub(crate) async fn refresh(&self, request_url: &Url, cached: &mut CachedResponse, not_modified_headers: &HeaderMap) -> Result<()> {
+        for (name, value) in not_modified_headers {
+            cached.response.headers.insert(name.clone(), value.clone());
+        }
+
+        cached.entry.headers = headers_to_pairs(&cached.response.headers);
+        cached.entry.stored_at = Utc::now();
+        self.write_entry(request_url, &cached.entry).await?;
+
+        debug!(url = %request_url, "Revalidated the cached response.");
+        Ok(())
+    }
+
+    async fn read_entry(&self, url: &Url) -> Result<Option<CachedResponse>> {
+        let entry_path = self.entry_path(url, "json");
+        let entry = match fs::read(&entry_path).await {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(Error::Io { path: entry_path, source }),
+        };
+        let entry: CacheEntry = serde_json::from_slice(&entry).map_err(|source| Error::Serialization { source })?;
+
+        // Two urls with the same hash.
+        if entry.request_url != url.as_str() {
+            return Ok(None);
+        }
+
+        let body_path = self.entry_path(url, "body");
+        let body = fs::read(&body_path).await.map_err(|source| Error::Io { path: body_path.clone(), source })?;
+
+        if entry.body_hash.is_some_and(|hash| hash != fnv1a(&body)) {
+            return Err(Error::Io {
+                path: body_path,
+                source: std::io::Error::new(std::io::ErrorKind::InvalidData, "the cached body doesn't match its entry"),
+            });
+        }
+
+        let response = FetchResponse {
+            url: Url::parse(&entry.url).map_err(|source| Error::InvalidUrl { url: entry.url.clone(), source })?,
+            status: StatusCode::from_u16(entry.status).unwrap_or(StatusCode::OK),
+            headers: pairs_to_headers(&entry.headers),
+            body,
+        };
+
+        Ok(Some(CachedResponse {
+            entry,
+            response,
+        }))
+    }
+
+    async fn write_entry(&self, url: &Url, entry: &CacheEntry) -> Result<()> {
+        let entry = serde_json::to_vec(entry).map_err(|source| Error::Serialization { source })?;
+        self.write_file(url, "json", &entry).await
+    }
+
+    // Writes to a temporary file and renames it into place. The temporary name is random, so concurrent writes of the same url don't mix.
+    async fn write_file(&self, url: &Url, extension: &str, contents: &[u8]) -> Result<()> {
+        let path = self.entry_path(url, extension);
+        let tmp_path = self.entry_path(url, &format!("{}.{:016x}.tmp", extension, rand::random::<u64>()));
+
+        fs::write(&tmp_path, contents).await.map_err(|source| Error::Io { path: tmp_path.clone(), source })?;
+        fs::rename(&tmp_path, &path).await.map_err(|source| Error::Io { path, source })
+    }
+
+    fn entry_path(&self, url: &Url, extension: &str) -> PathBuf {
+        self.policy.dir.join(format!("{:016x}.{}", fnv1a(url.as_str().as_bytes()), extension))
+    }
+}
+
+// Needs to stay the same across builds and Rust versions, which isn't a given for `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn headers_to_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect()
+}
+
+fn pairs_to_headers(pairs: &[(String, String)]) -> HeaderMap {
+    pairs.iter()
+        .filter_map(|(name, value)| Some((HeaderName::from_bytes(name.as_bytes()).ok()?, HeaderValue::from_str(value).ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use reqwest::{StatusCode, Url, header::{ETAG, HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED}};
+
+    use super::{CachePolicy, HttpCache, PageKind};
+    use crate::fetch::FetchResponse;
+
+    fn response(url: &Url) -> FetchResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+        FetchResponse {
+            url: url.clone(),
+            status: StatusCode::OK,
+            headers,
+            body: b"<html></html>".to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_revalidates_and_expires_entries() {
+        let dir = std::env::temp_dir().join(format!("bcz-cache-test-{}", std::process::id()));
+        let cache = HttpCache::new(CachePolicy {
+            artist_index_ttl: Duration::ZERO,
+            ..CachePolicy::new(&dir)
+        });
+        let url = Url::parse("https://ghost.bandcamp.com/music").unwrap();
+
+        assert!(cache.lookup(&url).await.is_none());
+        cache.store(&url, &response(&url)).await.unwrap();
+
+        let cached = cache.lookup(&url).await.unwrap();
+        assert_eq!(cached.response.body, b"<html></html>");
+        assert!(cache.is_fresh(&cached, PageKind::Artist));
+        assert!(!cache.is_fresh(&cached, PageKind::ArtistIndex));
+
+        let conditional_headers = cached.conditional_headers();
+        assert_eq!(conditional_headers[IF_NONE_MATCH], "\"v1\"");
+        assert_eq!(conditional_headers[IF_MODIFIED_SINCE], "Wed, 21 Oct 2015 07:28:00 GMT");
+
+        let mut not_modified_headers = HeaderMap::new();
+        not_modified_headers.insert(ETAG, HeaderValue::from_static("\"v2\""));
+        let mut refreshed = cached;
+        cache.refresh(&url, &mut refreshed, &not_modified_headers).await.unwrap();
+        assert_eq!(refreshed.response.body, b"<html></html>");
+        assert_eq!(refreshed.response.headers[ETAG], "\"v2\"");
+        assert_eq!(cache.lookup(&url).await.unwrap().response.headers[ETAG], "\"v2\"");
+
+        let other_url = Url::parse("https://ghost.bandcamp.com/album/meliora").unwrap();
+        assert!(cache.lookup(&other_url).await.is_none());
+
+        // Like a crash between writing the body and the metadata that goes with it.
+        fs::write(cache.entry_path(&url, "body"), "<html>a newer page</html>").unwrap();
+        assert!(cache.lookup(&url).await.is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use tokio::runtime::Handle;
+use tracing::{debug, error, info};
+
+use crate::{error::{Error, Result}, ScraperState};
+
+#[derive(Clone, Debug)]
+pub struct CheckpointPolicy {
+    pub path: PathBuf,
+    // Checkpoint after this many artists were added or scraped since the last checkpoint.
+    pub every_artists: Option<usize>,
+    // Checkpoint when at least this much time passed since the last checkpoint. Only checked when an artist gets added or scraped, since nothing changes in between.
+    pub every: Option<Duration>,
+}
+
+impl CheckpointPolicy {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            every_artists: Some(100),
+            every: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+struct CheckpointProgress {
+    last_checkpoint: Instant,
+    artists_since_last_checkpoint: usize,
+}
+
+// The state as JSON, numbered in the order the snapshots were taken.
+struct Snapshot {
+    number: u64,
+    json: Vec<u8>,
+    artists: usize,
+    next_artist_number: usize,
+}
+
+pub(crate) struct Checkpointer {
+    policy: CheckpointPolicy,
+    progress: Mutex<CheckpointProgress>,
+    snapshots_taken: AtomicU64,
+    // Number of the last snapshot that made it to disk. Holding it is what keeps two writes from racing each other.
+    last_written: Arc<Mutex<u64>>,
+}
+
+impl Checkpointer {
+    pub(crate) fn new(policy: CheckpointPolicy) -> Self {
+        Self {
+            policy,
+            progress: Mutex::new(CheckpointProgress {
+                last_checkpoint: Instant::now(),
+                artists_since_last_checkpoint: 0,
+            }),
+            snapshots_taken: AtomicU64::new(0),
+            last_written: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    // Called every time an artist changes in the state. The state is only locked while it's serialized; writing it happens on a blocking thread, so the crawl keeps going meanwhile. Failing to checkpoint isn't worth stopping a crawl for, so we only log it and try again at the next one.
+    pub(crate) fn artist_updated(&self, state: &RwLock<ScraperState>) {
+        let is_due = {
+            let mut progress = self.progress.lock().unwrap();
+            progress.artists_since_last_checkpoint += 1;
+
+            let is_due = self.policy.every_artists.is_some_and(|n| progress.artists_since_last_checkpoint >= n)
+                || self.policy.every.is_some_and(|every| progress.last_checkpoint.elapsed() >= every);
+
+            if is_due {
+                progress.last_checkpoint = Instant::now();
+                progress.artists_since_last_checkpoint = 0;
+            }
+
+            is_due
+        };
+
+        if !is_due {
+            return;
+        }
+
+        let snapshot = match self.snapshot(state) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!(error = %e, "Failed to serialize the scraper state for a checkpoint! Error: {}", e);
+                return;
+            }
+        };
+        let path = self.policy.path.clone();
+        let last_written = self.last_written.clone();
+        let write = move || {
+            if let Err(e) = write_snapshot(&path, &last_written, snapshot) {
+                error!(error = %e, "Failed to checkpoint the scraper state! Error: {}", e);
+            }
+        };
+
+        match Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(write);
+            }
+            Err(_) => write(),
+        }
+    }
+
+    // Writes the state right away and waits for it, including for any checkpoint still being written in the background.
+    pub(crate) fn checkpoint(&self, state: &RwLock<ScraperState>) -> Result<()> {
+        let snapshot = self.snapshot(state)?;
+
+        {
+            let mut progress = self.progress.lock().unwrap();
+            progress.last_checkpoint = Instant::now();
+            progress.artists_since_last_checkpoint = 0;
+        }
+
+        write_snapshot(&self.policy.path, &self.last_written, snapshot)
+    }
+
+    fn snapshot(&self, state: &RwLock<ScraperState>) -> Result<Snapshot> {
+        let state = state.read().unwrap();
+        let json = serde_json::to_vec(&*state).map_err(|source| Error::Serialization { source })?;
+
+        Ok(Snapshot {
+            // Taken while holding the lock, so a bigger number always means a newer state.
+            number: self.snapshots_taken.fetch_add(1, Ordering::Relaxed) + 1,
+            json,
+            artists: state.artists.len(),
+            next_artist_number: state.next_artist_number,
+        })
+    }
+}
+
+fn write_snapshot(path: &Path, last_written: &Mutex<u64>, snapshot: Snapshot) -> Result<()> {
+    let mut last_written = last_written.lock().unwrap();
+
+    // A background write that was waiting its turn while a newer checkpoint went through.
+    if *last_written > snapshot.number {
+        debug!(snapshot = snapshot.number, last_written = *last_written, "Skipping a checkpoint older than the one already on disk.");
+        return Ok(());
+    }
+
+    write_atomically(path, &snapshot.json)?;
+    *last_written = snapshot.number;
+
+    info!(path = %path.display(), artists = snapshot.artists, next_artist_number = snapshot.next_artist_number, "Checkpointed the scraper state.");
+    Ok(())
+}
+
+pub(crate) fn write_state_atomically(path: &Path, state: &ScraperState) -> Result<()> {
+    let json = serde_json::to_vec(state).map_err(|source| Error::Serialization { source })?;
+    write_atomically(path, &json)
+}
+
+// Writes to a temporary file next to `path` and renames it over `path` once it's fully on disk, so a crash at any point leaves either the old state or the new one.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_file_name = path.file_name().ok_or_else(|| Error::Io {
+        path: path.to_owned(),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidInput, "state path has no file name"),
+    })?.to_owned();
+    tmp_file_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_file_name);
+
+    debug!(tmp_path = %tmp_path.display(), "Writing the scraper state to a temporary file.");
+
+    let io_error = |source| Error::Io { path: tmp_path.clone(), source };
+
+    let mut tmp_file = File::create(&tmp_path).map_err(io_error)?;
+    tmp_file.write_all(contents).map_err(io_error)?;
+    tmp_file.sync_all().map_err(io_error)?;
+
+    fs::rename(&tmp_path, path).map_err(|source| Error::Io { path: path.to_owned(), source })?;
+
+    // The rename itself only survives a crash once the directory is synced too.
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        File::open(parent).and_then(|dir| dir.sync_all()).map_err(|source| Error::Io { path: parent.to_owned(), source })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::RwLock, time::Duration};
+
+    use super::{write_state_atomically, CheckpointPolicy, Checkpointer};
+    use crate::ScraperState;
+
+    #[test]
+    fn atomic_write_replaces_the_state_and_cleans_up() {
+        let dir = std::env::temp_dir().join(format!("bcz-checkpoint-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        fs::write(&path, "old state").unwrap();
+
+        let mut state = ScraperState::new();
+        state.next_artist_number = 42;
+        write_state_atomically(&path, &state).unwrap();
+
+        let loaded = ScraperState::load_from_file(&path).unwrap();
+        assert_eq!(loaded.next_artist_number, 42);
+        assert!(!dir.join("state.json.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn checkpoints_in_the_background_without_going_back_in_time() {
+        let dir = std::env::temp_dir().join(format!("bcz-background-checkpoint-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        let checkpointer = Checkpointer::new(CheckpointPolicy {
+            every_artists: Some(1),
+            every: None,
+            ..CheckpointPolicy::new(&path)
+        });
+        let state = RwLock::new(ScraperState::new());
+
+        state.write().unwrap().next_artist_number = 1;
+        checkpointer.artist_updated(&state);
+        // Queued behind the background write, and has to win over it.
+        state.write().unwrap().next_artist_number = 2;
+        checkpointer.checkpoint(&state).unwrap();
+        assert_eq!(ScraperState::load_from_file(&path).unwrap().next_artist_number, 2);
+
+        // Gives the background write time to finish, in case it was still waiting for its turn.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(ScraperState::load_from_file(&path).unwrap().next_artist_number, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Release, ReleaseKind};
+
+// Which rule decided a release's kind, kept on the release so misclassifications can be traced back.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ClassificationRule {
+    // A rule added with `ReleaseClassifier::rule`, by name.
+    Custom(String),
+    // Track pages only ever have one track.
+    TrackPage,
+    // The title ends with "EP" or "Single".
+    TitleHint,
+    // Few enough tracks, all of them short enough.
+    TrackCount,
+    // Too long to be anything but an album.
+    Runtime,
+}
+
+type CustomRule = Arc<dyn Fn(&Release) -> Option<ReleaseKind> + Send + Sync>;
+
+// Tells singles, EPs and albums apart, since Bandcamp itself only knows about albums and tracks. The default thresholds follow the definitions most distributors use:
+// https://support.tunecore.com/hc/en-us/articles/115006689928-What-is-the-difference-between-a-Single-an-EP-and-an-Album-
+// https://support.symdistro.com/hc/en-us/articles/215985603-What-is-the-difference-between-Single-EP-and-Album-
+#[derive(Clone)]
+pub struct ReleaseClassifier {
+    pub use_title_hints: bool,
+    pub single_max_tracks: usize,
+    // A single with any track longer than this is an EP or an album instead.
+    pub single_max_track_runtime: Duration,
+    pub ep_max_tracks: usize,
+    // Singles and EPs both have to be shorter than this.
+    pub max_runtime: Duration,
+    custom_rules: Vec<(String, CustomRule)>,
+}
+
+impl Default for ReleaseClassifier {
+    fn default() -> Self {
+        Self {
+            use_title_hints: true,
+            single_max_tracks: 3,
+            single_max_track_runtime: Duration::from_secs(10 * 60),
+            ep_max_tracks: 6,
+            max_runtime: Duration::from_secs(30 * 60),
+            custom_rules: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ReleaseClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReleaseClassifier")
+            .field("use_title_hints", &self.use_title_hints)
+            .field("single_max_tracks", &self.single_max_tracks)
+            .field("single_max_track_runtime", &self.single_max_track_runtime)
+            .field("ep_max_tracks", &self.ep_max_tracks)
+            .field("max_runtime", &self.max_runtime)
+            .field("custom_rules", &self.custom_rules.iter().map(|(name, _)| name).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl ReleaseClassifier {
+    // Custom rules run before the built-in ones, in the order they were added. The first one to return a kind wins.
+    pub fn rule(mut self, name: impl Into<String>, rule: impl Fn(&Release) -> Option<ReleaseKind> + Send + Sync + 'static) -> Self {
+        self.custom_rules.push((name.into(), Arc::new(rule)));
+        self
+    }
+
+    pub fn classify(&self, release: &Release) -> (ReleaseKind, ClassificationRule) {
+        for (name, rule) in &self.custom_rules {
+            if let Some(kind) = rule(release) {
+                return (kind, ClassificationRule::Custom(name.clone()));
+            }
+        }
+
+        if release.url.contains("/track/") {
+            return (ReleaseKind::Single, ClassificationRule::TrackPage);
+        }
+
+        if self.use_title_hints {
+            if let Some(kind) = title_hint(&release.name) {
+                return (kind, ClassificationRule::TitleHint);
+            }
+        }
+
+        // Tracks without a duration (e.g. on preorders) just don't count towards the runtime.
+        if release.runtime() >= self.max_runtime {
+            return (ReleaseKind::Album, ClassificationRule::Runtime);
+        }
+
+        let track_count = release.tracks.len();
+
+        if track_count <= self.single_max_tracks && release.tracks.iter().all(|t| t.duration.unwrap_or_default() < self.single_max_track_runtime) {
+            (ReleaseKind::Single, ClassificationRule::TrackCount)
+        } else if track_count <= self.ep_max_tracks {
+            (ReleaseKind::EP, ClassificationRule::TrackCount)
+        } else {
+            (ReleaseKind::Album, ClassificationRule::TrackCount)
+        }
+    }
+
+    // Sets the release's kind, along with the rule that picked it.
+    pub fn apply(&self, release: &mut Release) {
+        let (kind, rule) = self.classify(release);
+        release.kind = kind;
+        release.classified_by = Some(rule);
+    }
+}
+
+// Only looks at the last word, so "Single Ladies" stays out of it but "Nightmare (Single)" and "Seven Inches of Satanic Panic EP" don't.
+fn title_hint(title: &str) -> Option<ReleaseKind> {
+    let last_word = title.split(|c: char| !c.is_alphanumeric()).rfind(|w| !w.is_empty())?;
+
+    match last_word.to_lowercase().as_str() {
+        "ep" => Some(ReleaseKind::EP),
+        "single" => Some(ReleaseKind::Single),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClassificationRule, ReleaseClassifier};
+    use crate::{parse_duration, Release, ReleaseKind, Track};
+
+    fn release(url: &str, name: &str, durations: &[&str]) -> Release {
+        Release {
+            kind: ReleaseKind::Album,
+            name: name.to_owned(),
+            artist: "Ghost".to_owned(),
+            url: url.to_owned(),
+            album_id: None,
+            release_date: None,
+            label: None,
+            tags: Vec::new(),
+            cover_art_url: None,
+            formats: Vec::new(),
+            physical_formats: Vec::new(),
+            pricing: None,
+            tracks: durations.iter().enumerate().map(|(i, duration)| Track {
+                id: None,
+                index: i + 1,
+                name: format!("Track {}", i + 1),
+                duration: parse_duration(duration),
+                pricing: None,
+            }).collect(),
+            classified_by: None,
+        }
+    }
+
+    #[test]
+    fn classifies_by_track_count_and_runtime() {
+        let classifier = ReleaseClassifier::default();
+        let album_url = "https://ghost.bandcamp.com/album/x";
+
+        assert_eq!(classifier.classify(&release(album_url, "Kiss the Go-Goat", &["03:15", "04:02"])), (ReleaseKind::Single, ClassificationRule::TrackCount));
+        // A long track makes it more than a single.
+        assert_eq!(classifier.classify(&release(album_url, "Popestar", &["12:00", "04:00"])), (ReleaseKind::EP, ClassificationRule::TrackCount));
+        assert_eq!(classifier.classify(&release(album_url, "Popestar", &["04:00"; 5])), (ReleaseKind::EP, ClassificationRule::TrackCount));
+        assert_eq!(classifier.classify(&release(album_url, "Opus Eponymous", &["04:00"; 7])), (ReleaseKind::Album, ClassificationRule::TrackCount));
+        assert_eq!(classifier.classify(&release(album_url, "Infestissumam", &["16:00", "15:00"])), (ReleaseKind::Album, ClassificationRule::Runtime));
+        // Unknown durations count as nothing.
+        assert_eq!(classifier.classify(&release(album_url, "Impera", &["03:17", "", ""])), (ReleaseKind::Single, ClassificationRule::TrackCount));
+    }
+
+    #[test]
+    fn track_pages_and_title_hints_win_over_track_counts() {
+        let classifier = ReleaseClassifier::default();
+
+        assert_eq!(classifier.classify(&release("https://ghost.bandcamp.com/track/x", "Secular Haze", &["04:57"])), (ReleaseKind::Single, ClassificationRule::TrackPage));
+        assert_eq!(classifier.classify(&release("https://ghost.bandcamp.com/album/x", "If You Have Ghost EP", &["04:00"; 2])), (ReleaseKind::EP, ClassificationRule::TitleHint));
+        assert_eq!(classifier.classify(&release("https://ghost.bandcamp.com/album/x", "Mary on a Cross (Single)", &["04:00"; 4])), (ReleaseKind::Single, ClassificationRule::TitleHint));
+        assert_eq!(classifier.classify(&release("https://ghost.bandcamp.com/album/x", "Single Ladies", &["04:00"; 4])), (ReleaseKind::EP, ClassificationRule::TrackCount));
+
+        let classifier = ReleaseClassifier {
+            use_title_hints: false,
+            ..Default::default()
+        };
+        assert_eq!(classifier.classify(&release("https://ghost.bandcamp.com/album/x", "If You Have Ghost EP", &["04:00"; 2])), (ReleaseKind::Single, ClassificationRule::TrackCount));
+    }
+
+    #[test]
+    fn custom_rules_run_first() {
+        let classifier = ReleaseClassifier::default()
+            .rule("no singles", |r| (r.tracks.len() == 1).then_some(ReleaseKind::Album));
+        let mut release = release("https://ghost.bandcamp.com/track/x", "Secular Haze", &["04:57"]);
+
+        classifier.apply(&mut release);
+
+        assert_eq!(release.kind, ReleaseKind::Album);
+        assert_eq!(release.classified_by, Some(ClassificationRule::Custom("no singles".to_owned())));
+    }
+}
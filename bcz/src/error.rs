@@ -0,0 +1,141 @@
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    // The request couldn't be sent, or the response couldn't be read.
+    Http {
+        url: String,
+        source: reqwest::Error,
+    },
+    // Like `Http`, for fetchers that don't use reqwest, e.g. when a proxy refused the connection.
+    Transport {
+        url: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    Timeout {
+        url: String,
+    },
+    // Any non-successful status that doesn't have a more specific variant.
+    UnexpectedStatus {
+        url: String,
+        status: u16,
+    },
+    NotFound {
+        url: String,
+    },
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    // The server is overloaded or under maintenance (a 503).
+    Unavailable {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+    // Replaying a crawl asked for a page that was never archived.
+    NotArchived {
+        url: String,
+    },
+    // The page didn't have what we expected at `selector`, which usually means Bandcamp changed its markup.
+    Parse {
+        url: String,
+        selector: String,
+    },
+    // Something in the state disagrees with what we just scraped, e.g. an artist we've already stored.
+    StateConflict {
+        key: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // The state couldn't be turned into JSON or back.
+    Serialization {
+        source: serde_json::Error,
+    },
+}
+
+impl Error {
+    pub(crate) fn parse(url: impl Into<String>, selector: impl Into<String>) -> Self {
+        Error::Parse {
+            url: url.into(),
+            selector: selector.into(),
+        }
+    }
+
+    // For `Fetcher`s to report that a request couldn't be sent or its response couldn't be read, whatever they use to send it.
+    pub fn transport(url: impl Into<String>, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Transport {
+            url: url.into(),
+            source: source.into(),
+        }
+    }
+
+    // Whether trying the same request again later could succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Http { .. } | Error::Transport { .. } | Error::Timeout { .. } | Error::RateLimited { .. } | Error::Unavailable { .. } => true,
+            Error::UnexpectedStatus { status, .. } => *status == 408 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    // How long the server asked us to wait before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } | Error::Unavailable { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_reqwest(url: impl Into<String>, source: reqwest::Error) -> Self {
+        let url = url.into();
+
+        if source.is_timeout() {
+            Error::Timeout { url }
+        } else {
+            Error::Http { url, source }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http { url, source } => write!(f, "request to {} failed: {}", url, source),
+            Error::Transport { url, source } => write!(f, "request to {} failed: {}", url, source),
+            Error::Timeout { url } => write!(f, "request to {} timed out", url),
+            Error::UnexpectedStatus { url, status } => write!(f, "request to {} returned status {}", url, status),
+            Error::NotFound { url } => write!(f, "{} was not found", url),
+            Error::RateLimited { url, retry_after: Some(retry_after) } => write!(f, "rate limited while requesting {}, retry after {:?}", url, retry_after),
+            Error::RateLimited { url, retry_after: None } => write!(f, "rate limited while requesting {}", url),
+            Error::Unavailable { url, retry_after: Some(retry_after) } => write!(f, "{} is unavailable, retry after {:?}", url, retry_after),
+            Error::Unavailable { url, retry_after: None } => write!(f, "{} is unavailable", url),
+            Error::InvalidUrl { url, source } => write!(f, "invalid url {}: {}", url, source),
+            Error::NotArchived { url } => write!(f, "{} isn't in the archive", url),
+            Error::Parse { url, selector } => write!(f, "couldn't find {} in {}", selector, url),
+            Error::StateConflict { key } => write!(f, "{} is already in the scraper state", key),
+            Error::Io { path, source } => write!(f, "i/o error on {}: {}", path.display(), source),
+            Error::Serialization { source } => write!(f, "couldn't (de)serialize the scraper state: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http { source, .. } => Some(source),
+            Error::Transport { source, .. } => Some(source.as_ref()),
+            Error::InvalidUrl { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Serialization { source } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode, Url, header::HeaderMap};
+
+pub use crate::replay::{ReplayFetcher, ReplayMiss};
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug)]
+pub struct FetchRequest {
+    // Already includes the query string.
+    pub url: Url,
+    pub headers: HeaderMap,
+}
+
+impl FetchRequest {
+    pub fn get(url: Url) -> Self {
+        Self {
+            url,
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FetchResponse {
+    // Where the response actually came from, after any redirects.
+    pub url: Url,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+// The transport used by `BcScraper` for every request. Implementations only move bytes around: they should return non-successful statuses as responses, and leave rate limiting, retries and status handling to the scraper. When the request itself fails (e.g. the connection got refused), return `Error::transport` so the scraper retries it.
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchResponse>;
+}
+
+pub struct ReqwestFetcher {
+    client: Client,
+}
+
+impl ReqwestFetcher {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchResponse> {
+        let url = request.url.to_string();
+
+        let response = self.client.get(request.url)
+            .headers(request.headers)
+            .send()
+            .await
+            .map_err(|e| Error::from_reqwest(&url, e))?;
+
+        let url = response.url().clone();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.map_err(|e| Error::from_reqwest(url.as_str(), e))?.to_vec();
+
+        Ok(FetchResponse {
+            url,
+            status,
+            headers,
+            body,
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use std::{future::Future, sync::Arc, time::{Duration, SystemTime}};
+
+use reqwest::{StatusCode, Url, header::{HeaderMap, RETRY_AFTER}};
+use tokio::{task, time::sleep};
+use tracing::{debug, error, warn};
+
+use crate::{cache::{HttpCache, PageKind}, error::{Error, Result}, fetch::{FetchRequest, FetchResponse, Fetcher}, rate_limit::RateLimiter, retry::RetryPolicy, warc::WarcWriter};
+
+// Every request the crate makes goes through here, so it's where all the politeness policies get applied.
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    fetcher: Arc<dyn Fetcher>,
+    rate_limiter: Arc<RateLimiter>,
+    retry_policy: RetryPolicy,
+    cache: Option<Arc<HttpCache>>,
+    warc: Option<Arc<WarcWriter>>,
+}
+
+impl HttpClient {
+    pub(crate) fn new(fetcher: Arc<dyn Fetcher>, rate_limiter: RateLimiter, retry_policy: RetryPolicy, cache: Option<HttpCache>, warc: Option<WarcWriter>) -> Self {
+        Self {
+            fetcher,
+            rate_limiter: Arc::new(rate_limiter),
+            retry_policy,
+            cache: cache.map(Arc::new),
+            warc: warc.map(Arc::new),
+        }
+    }
+
+    pub(crate) fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    // Fresh cached pages are returned without touching the network. Stale ones are revalidated with a conditional request.
+    pub(crate) async fn get(&self, url: Url, kind: PageKind) -> Result<FetchResponse> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.with_retries(&url, || self.send(url.clone(), HeaderMap::new())).await,
+        };
+
+        let cached = cache.lookup(&url).await;
+
+        if let Some(cached) = cached.as_ref().filter(|cached| cache.is_fresh(cached, kind)) {
+            debug!(%url, age = ?cached.age(), "Using the cached response.");
+            return Ok(cached.response.clone());
+        }
+
+        let headers = cached.as_ref().map(|cached| cached.conditional_headers()).unwrap_or_default();
+        let response = self.with_retries(&url, || self.send(url.clone(), headers.clone())).await?;
+
+        match cached {
+            Some(mut cached) if response.status == StatusCode::NOT_MODIFIED => {
+                // The body we have is still the right one, even if we couldn't write down that it is.
+                if let Err(e) = cache.refresh(&url, &mut cached, &response.headers).await {
+                    warn!(%url, error = %e, "Couldn't update the cached response. Error: {}", e);
+                }
+
+                Ok(cached.response)
+            }
+            // We only send conditional requests when we have something cached, so the server is confused.
+            None if response.status == StatusCode::NOT_MODIFIED => Err(Error::UnexpectedStatus { url: url.to_string(), status: response.status.as_u16() }),
+            _ => {
+                if let Err(e) = cache.store(&url, &response).await {
+                    warn!(%url, error = %e, "Couldn't cache the response. Error: {}", e);
+                }
+
+                Ok(response)
+            }
+        }
+    }
+
+    pub(crate) async fn get_text(&self, url: Url, kind: PageKind) -> Result<String> {
+        Ok(self.get(url, kind).await?.text())
+    }
+
+    async fn send(&self, url: Url, headers: HeaderMap) -> Result<FetchResponse> {
+        self.rate_limiter.acquire(url.host_str()).await;
+
+        let request = FetchRequest { url, headers };
+        let mut response = self.fetcher.fetch(request.clone()).await?;
+
+        // Failed responses are archived too, since they're also part of what the crawl saw. Writing them blocks, so it happens on a blocking thread instead of holding up every other request on this worker.
+        if let Some(warc) = self.warc.clone() {
+            let url = request.url.clone();
+            let archived;
+            (response, archived) = task::spawn_blocking(move || {
+                let archived = warc.write_exchange(&request, &response);
+                (response, archived)
+            }).await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+
+            if let Err(e) = archived {
+                error!(%url, error = %e, "Couldn't archive the response. Error: {}", e);
+            }
+        }
+
+        check_status(response)
+    }
+
+    async fn with_retries<T, F, Fut>(&self, url: &Url, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+
+        loop {
+            match f().await {
+                Ok(v) => return Ok(v),
+                Err(e) => match self.retry_policy.delay_after(attempt, &e) {
+                    Some(delay) => {
+                        warn!(%url, attempt, ?delay, error = %e, "Request failed, will try again in {:?}. Error: {}", delay, e);
+                        sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+fn check_status(response: FetchResponse) -> Result<FetchResponse> {
+    let url = response.url.to_string();
+
+    match response.status {
+        s if s.is_success() => Ok(response),
+        // Only sent back to conditional requests, which `get` deals with.
+        StatusCode::NOT_MODIFIED => Ok(response),
+        StatusCode::NOT_FOUND => Err(Error::NotFound { url }),
+        StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+            url,
+            retry_after: retry_after(&response.headers),
+        }),
+        StatusCode::SERVICE_UNAVAILABLE => Err(Error::Unavailable {
+            url,
+            retry_after: retry_after(&response.headers),
+        }),
+        s => Err(Error::UnexpectedStatus {
+            url,
+            status: s.as_u16(),
+        }),
+    }
+}
+
+// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
@@ -1,27 +1,166 @@
-use std::{collections::HashMap, sync::{Arc, RwLock}};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, sync::{Arc, RwLock}, time::Duration};
 
-use bc_artist_directory::ArtistUrl;
-use chrono::{DateTime, Utc, TimeZone};
+use bc_artist_directory::{canonical_artist_url, ArtistUrl};
+use chrono::{DateTime, NaiveDate, Utc, TimeZone};
 use serde::{Deserialize, Serialize};
 
+pub use cache::CachePolicy;
+pub use classify::{ClassificationRule, ReleaseClassifier};
+pub use checkpoint::CheckpointPolicy;
+pub use reqwest::Url;
+pub use error::{Error, Result};
+pub use rate_limit::RateLimit;
+pub use retry::RetryPolicy;
+pub use warc::WarcPolicy;
+
 pub mod bc;
 mod bc_artist_directory;
 mod bc_artist_page;
 mod bc_release_page;
+mod cache;
+mod classify;
+mod checkpoint;
+mod error;
+pub mod fetch;
+mod http;
+mod rate_limit;
+mod replay;
+mod retry;
+mod warc;
 
-static BANDCAMP_DISCOGRAPHY_PATH: &'static str = "/music";
+// Defaults for `BcScraperBuilder`, which can point the scraper somewhere else.
+static BANDCAMP_BASE_URL: &str = "https://bandcamp.com";
+static BANDCAMP_ARTIST_INDEX_PATH: &str = "/artist_index";
+static BANDCAMP_DISCOGRAPHY_PATH: &str = "/music";
 
 pub(crate) type RuntimeScraperState = Arc<RwLock<ScraperState>>;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Track {
+    // Bandcamp's own id for the track, when the release page exposes it.
+    #[serde(default)]
+    pub id: Option<u64>,
     pub index: usize,
     pub name: String,
-    pub duration: String,
+    // Stored as seconds. Tracks that can't be streamed (e.g. on preorders) don't have one.
+    #[serde(serialize_with = "serialize_duration_seconds", deserialize_with = "deserialize_duration")]
+    pub duration: Option<Duration>,
+    // Bandcamp only shows what a track costs on its own page, so the tracks of an album don't have one.
+    #[serde(default)]
+    pub pricing: Option<Pricing>,
+}
+
+fn serialize_duration_seconds<S: serde::Serializer>(duration: &Option<Duration>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    duration.map(|d| d.as_secs_f64()).serialize(serializer)
+}
+
+// Older state files have the duration as shown on the page, e.g. "04:57", with an empty string when there was none.
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum SerializedDuration {
+        Seconds(f64),
+        Text(String),
+    }
+
+    Ok(match Option::<SerializedDuration>::deserialize(deserializer)? {
+        Some(SerializedDuration::Seconds(seconds)) => Duration::try_from_secs_f64(seconds).ok(),
+        Some(SerializedDuration::Text(text)) => parse_duration(&text),
+        None => None,
+    })
+}
+
+// Parses durations the way release pages show them, "04:57" or "1:02:03". Only the first part can go past 59, and anything that doesn't fit is treated as unparseable rather than overflowing.
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return None;
+    }
+
+    let seconds = text.split(':').enumerate().try_fold(0u64, |total, (i, part)| {
+        let part = part.trim().parse::<u64>().ok().filter(|part| i == 0 || *part < 60)?;
+        total.checked_mul(60)?.checked_add(part)
+    })?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum ReleaseKind {
+    Single,
+    EP,
+    Album,
+}
+
+// Ways a release can be bought, as listed on its page.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ReleaseFormat {
+    Digital,
+    CompactDisc,
+    Cassette,
+    Vinyl,
+    // Anything else, with the title Bandcamp shows for it.
+    Other(String),
+}
+
+// A physical edition of a release that's for sale, from its entry in the release page's buy section.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PhysicalFormat {
+    pub format: ReleaseFormat,
+    // The package title, e.g. "Black 12\" Vinyl LP", which is what tells variants of the same format apart.
+    pub description: String,
+    pub price: Option<f64>,
+    // As Bandcamp shows it, e.g. "EUR".
+    pub currency: Option<String>,
+    pub sold_out: bool,
+    // Only shown when few are left.
+    pub remaining: Option<u32>,
+    // When it ships, or when it comes out for preorders.
+    pub ship_date: Option<NaiveDate>,
+}
+
+// What buying the digital release costs.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Pricing {
+    // Zero for free and most name your price releases.
+    pub minimum_price: Option<f64>,
+    // As Bandcamp shows it, e.g. "USD".
+    pub currency: Option<String>,
+    // Fans can pay more than the minimum.
+    pub name_your_price: bool,
+    // Can be downloaded without paying, sometimes in exchange for an email address.
+    pub free: bool,
+    pub preorder: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Release {
+    pub kind: ReleaseKind,
+    pub name: String,
+    pub artist: String,
+    pub url: String,
+    // Bandcamp's id for the album, or for the track on track pages.
+    pub album_id: Option<u64>,
+    pub release_date: Option<DateTime<Utc>>,
+    // Only set for releases put out through a label's Bandcamp page.
+    pub label: Option<String>,
+    pub tags: Vec<String>,
+    pub cover_art_url: Option<String>,
+    pub formats: Vec<ReleaseFormat>,
+    #[serde(default)]
+    pub physical_formats: Vec<PhysicalFormat>,
+    // Missing when the page doesn't sell the release digitally.
+    #[serde(default)]
+    pub pricing: Option<Pricing>,
+    pub tracks: Vec<Track>,
+    // Set once a `ReleaseClassifier` decided the kind. Releases straight out of the parser only tell tracks and albums apart.
+    #[serde(default)]
+    pub classified_by: Option<ClassificationRule>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub enum Release {
+// Releases used to be an enum with a variant per kind, which is what older state files still have.
+#[derive(Deserialize)]
+enum LegacyRelease {
     Single {
         name: String,
         tracks: Vec<Track>,
@@ -36,26 +175,96 @@ pub enum Release {
     },
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedRelease {
+    Current(Box<Release>),
+    Legacy(LegacyRelease),
+}
+
+impl Release {
+    // Sum of every track's duration. Tracks without one don't count.
+    pub fn runtime(&self) -> Duration {
+        self.tracks.iter().filter_map(|t| t.duration).sum()
+    }
+}
+
+impl From<SerializedRelease> for Release {
+    fn from(release: SerializedRelease) -> Self {
+        let (kind, name, tracks) = match release {
+            SerializedRelease::Current(release) => return *release,
+            SerializedRelease::Legacy(LegacyRelease::Single { name, tracks }) => (ReleaseKind::Single, name, tracks),
+            SerializedRelease::Legacy(LegacyRelease::Album { name, tracks }) => (ReleaseKind::Album, name, tracks),
+            SerializedRelease::Legacy(LegacyRelease::EP { name, tracks }) => (ReleaseKind::EP, name, tracks),
+        };
+
+        Release {
+            kind,
+            name,
+            artist: String::new(),
+            url: String::new(),
+            album_id: None,
+            release_date: None,
+            label: None,
+            tags: Vec::new(),
+            cover_art_url: None,
+            formats: Vec::new(),
+            physical_formats: Vec::new(),
+            pricing: None,
+            tracks,
+            classified_by: None,
+        }
+    }
+}
+
+fn deserialize_releases<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<Release>, D::Error> {
+    let releases: Vec<SerializedRelease> = Deserialize::deserialize(deserializer)?;
+    Ok(releases.into_iter().map(Release::from).collect())
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ArtistDiscography {
+    #[serde(deserialize_with = "deserialize_releases")]
     pub albums: Vec<Release>,
+    #[serde(deserialize_with = "deserialize_releases")]
     pub eps: Vec<Release>,
+    #[serde(deserialize_with = "deserialize_releases")]
     pub singles: Vec<Release>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl ArtistDiscography {
+    pub fn add_release(&mut self, release: Release) {
+        match release.kind {
+            ReleaseKind::Album => self.albums.push(release),
+            ReleaseKind::EP => self.eps.push(release),
+            ReleaseKind::Single => self.singles.push(release),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ArtistInfo {
     pub name: String,
     pub url: String,
+    #[serde(default)]
+    pub band_id: Option<u64>,
     pub discography: ArtistDiscography,
     pub last_scrape_completed_on: DateTime<Utc>,
 }
 
+impl ArtistInfo {
+    // Artists only found in the index yet still have the placeholder date from `From<ArtistUrl>`.
+    pub fn has_been_scraped(&self) -> bool {
+        self.last_scrape_completed_on.timestamp_millis() != 0
+    }
+}
+
 impl From<ArtistUrl> for ArtistInfo {
     fn from(artist_url: ArtistUrl) -> Self {
         ArtistInfo {
             name: artist_url.name,
             url: artist_url.url,
+            band_id: artist_url.band_id,
             discography: Default::default(),
             last_scrape_completed_on: Utc.timestamp_millis(0),
         }
@@ -63,9 +272,52 @@ impl From<ArtistUrl> for ArtistInfo {
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(from = "SerializedScraperState")]
 pub struct ScraperState {
+    // Keyed by the artist's canonical url, since different artists can share the same name.
     pub artists: HashMap<String, ArtistInfo>,
     pub next_artist_number: usize,
+    // Set once the `Artists` stream walked past the last page of the artist index.
+    pub artist_index_completed: bool,
+    // Lowercased artist name to the keys of every artist with that name. Derived from `artists`, so it isn't saved.
+    #[serde(skip)]
+    artist_keys_by_name: HashMap<String, Vec<String>>,
+    #[serde(skip)]
+    artist_keys_by_band_id: HashMap<u64, String>,
+}
+
+#[derive(Deserialize)]
+struct SerializedScraperState {
+    artists: HashMap<String, ArtistInfo>,
+    next_artist_number: usize,
+    #[serde(default)]
+    artist_index_completed: bool,
+}
+
+impl From<SerializedScraperState> for ScraperState {
+    fn from(serialized: SerializedScraperState) -> Self {
+        let mut state = ScraperState {
+            artists: HashMap::new(),
+            next_artist_number: serialized.next_artist_number,
+            artist_index_completed: serialized.artist_index_completed,
+            artist_keys_by_name: HashMap::new(),
+            artist_keys_by_band_id: HashMap::new(),
+        };
+
+        // Older states were keyed by artist name, so we always rebuild the keys from the urls. Urls that used to be told apart (e.g. by their scheme) can end up with the same key, in which case the most recently scraped one wins.
+        for artist in serialized.artists.into_values() {
+            let key = canonical_artist_url(&artist.url);
+
+            if state.artists.get(&key).is_some_and(|existing| existing.last_scrape_completed_on >= artist.last_scrape_completed_on) {
+                continue;
+            }
+
+            state.index_artist(&artist.name, artist.band_id, &key);
+            state.artists.insert(key, artist);
+        }
+
+        state
+    }
 }
 
 impl ScraperState {
@@ -73,20 +325,308 @@ impl ScraperState {
         Self {
             artists: HashMap::new(),
             next_artist_number: 0,
+            artist_index_completed: false,
+            artist_keys_by_name: HashMap::new(),
+            artist_keys_by_band_id: HashMap::new(),
+        }
+    }
+
+    fn index_artist(&mut self, name: &str, band_id: Option<u64>, key: &str) {
+        let keys = self.artist_keys_by_name.entry(name.to_lowercase()).or_default();
+
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_owned());
+        }
+
+        if let Some(band_id) = band_id {
+            self.artist_keys_by_band_id.insert(band_id, key.to_owned());
+        }
+    }
+
+    fn unindex_artist(&mut self, name: &str, band_id: Option<u64>, key: &str) {
+        if let Some(keys) = self.artist_keys_by_name.get_mut(&name.to_lowercase()) {
+            keys.retain(|k| k != key);
+        }
+
+        if let Some(band_id) = band_id {
+            if self.artist_keys_by_band_id.get(&band_id).is_some_and(|k| k == key) {
+                self.artist_keys_by_band_id.remove(&band_id);
+            }
+        }
+    }
+
+    // The key an artist is (or would be) stored under. An artist we already know by band id keeps its existing key, even if we now reached it through a different url.
+    fn artist_key(&self, artist_url: &ArtistUrl) -> String {
+        artist_url.band_id
+            .and_then(|band_id| self.artist_keys_by_band_id.get(&band_id).cloned())
+            .unwrap_or_else(|| artist_url.canonical_url())
+    }
+
+    pub fn artist(&self, artist_url: &ArtistUrl) -> Option<&ArtistInfo> {
+        self.artists.get(&self.artist_key(artist_url))
+    }
+
+    pub fn artist_by_band_id(&self, band_id: u64) -> Option<&ArtistInfo> {
+        self.artists.get(self.artist_keys_by_band_id.get(&band_id)?)
+    }
+
+    // Every artist with this name, ignoring case.
+    pub fn artists_by_name(&self, name: &str) -> Vec<&ArtistInfo> {
+        self.artist_keys_by_name.get(&name.to_lowercase())
+            .map(|keys| keys.iter().filter_map(|key| self.artists.get(key)).collect())
+            .unwrap_or_default()
+    }
+
+    // Artists we found in the index but didn't get to scrape yet, e.g. because the last crawl was interrupted.
+    pub fn unscraped_artists(&self) -> Vec<ArtistUrl> {
+        self.artists.values()
+            .filter(|artist| !artist.has_been_scraped())
+            .map(|artist| ArtistUrl {
+                name: artist.name.clone(),
+                url: artist.url.clone(),
+                band_id: artist.band_id,
+            })
+            .collect()
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let state_file = File::open(path).map_err(|source| Error::Io { path: path.to_owned(), source })?;
+
+        serde_json::from_reader(BufReader::new(state_file)).map_err(|source| Error::Serialization { source })
+    }
+
+    // Never leaves a half-written file behind, even if the process dies while saving.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        checkpoint::write_state_atomically(path.as_ref(), self)
+    }
+
+    pub fn new_artist_from_url(&mut self, artist_url: ArtistUrl) -> Result<()> {
+        let key = self.artist_key(&artist_url);
+
+        if self.artists.contains_key(&key) {
+            return Err(Error::StateConflict { key });
         }
+
+        self.index_artist(&artist_url.name, artist_url.band_id, &key);
+        self.artists.insert(key, artist_url.into());
+        Ok(())
     }
 
-    pub fn new_artist_from_url(&mut self, artist_url: ArtistUrl) {
-        assert!(!self.artists.contains_key(&artist_url.name));
-        self.artists.insert(artist_url.name.clone(), artist_url.into());
+    // Moves an artist we stored under one url to the url it resolved to, e.g. from its custom domain to its `*.bandcamp.com` subdomain, and records its band id. If an artist is already stored under the new url, the old entry is only dropped when both have the same band id. Otherwise they might be different artists, so the artist stays where it was. Returns the url the artist is stored under afterwards.
+    pub fn move_artist(&mut self, from: &ArtistUrl, to: &ArtistUrl) -> ArtistUrl {
+        let old_key = self.artist_key(from);
+        let new_key = self.artist_key(to);
+
+        if old_key != new_key {
+            let existing_band_id = self.artists.get(&new_key).map(|artist| artist.band_id);
+
+            if let Some(mut artist) = self.artists.remove(&old_key) {
+                match existing_band_id {
+                    Some(existing_band_id) if existing_band_id.is_none() || existing_band_id != to.band_id => {
+                        tracing::warn!(%old_key, %new_key, ?existing_band_id, band_id = ?to.band_id, "Another artist is already stored under the url this one resolved to, keeping them apart.");
+                        artist.band_id = artist.band_id.or(to.band_id);
+                        let stayed = ArtistUrl {
+                            name: artist.name.clone(),
+                            url: artist.url.clone(),
+                            band_id: artist.band_id,
+                        };
+                        self.index_artist(&artist.name, artist.band_id, &old_key);
+                        self.artists.insert(old_key, artist);
+                        return stayed;
+                    }
+                    Some(_) => self.unindex_artist(&artist.name, artist.band_id, &old_key),
+                    None => {
+                        self.unindex_artist(&artist.name, artist.band_id, &old_key);
+                        artist.url = to.url.clone();
+                        artist.band_id = to.band_id.or(artist.band_id);
+                        self.artists.insert(new_key.clone(), artist);
+                    }
+                }
+            }
+        }
+
+        if let Some(artist) = self.artists.get_mut(&new_key) {
+            artist.band_id = artist.band_id.or(to.band_id);
+            let (name, band_id) = (artist.name.clone(), artist.band_id);
+            self.index_artist(&name, band_id, &new_key);
+        }
+
+        to.clone()
+    }
+
+    // Stores a freshly scraped discography for the artist, adding the artist to the state if we haven't seen it yet.
+    pub fn update_artist_discography(&mut self, artist_url: &ArtistUrl, discography: ArtistDiscography) {
+        let key = self.artist_key(artist_url);
+        self.index_artist(&artist_url.name, artist_url.band_id, &key);
+
+        let artist = self.artists.entry(key).or_insert_with(|| artist_url.clone().into());
+        artist.band_id = artist.band_id.or(artist_url.band_id);
+        artist.discography = discography;
+        artist.last_scrape_completed_on = Utc::now();
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use crate::{bc_artist_directory::ArtistUrl, parse_duration, ArtistDiscography, ReleaseKind, ScraperState};
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn parses_durations_without_overflowing() {
+        assert_eq!(parse_duration("04:57"), Some(Duration::from_secs(297)));
+        assert_eq!(parse_duration(" 1:02:03 "), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("75:00"), Some(Duration::from_secs(4500)));
+        assert_eq!(parse_duration("04:60"), None);
+        assert_eq!(parse_duration("1:99:00"), None);
+        assert_eq!(parse_duration("18446744073709551615:00"), None);
+        assert_eq!(parse_duration("1:".repeat(20).trim_end_matches(':')), None);
+        assert_eq!(parse_duration("four minutes"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    fn artist_url(name: &str, url: &str) -> ArtistUrl {
+        ArtistUrl {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            band_id: None,
+        }
+    }
+
+    #[test]
+    fn artists_with_the_same_name_are_kept_apart() {
+        let mut state = ScraperState::new();
+        state.new_artist_from_url(artist_url("Ghost", "https://ghost.bandcamp.com")).unwrap();
+        state.new_artist_from_url(artist_url("Ghost", "https://ghostband.bandcamp.com/?from=artist_index")).unwrap();
+
+        assert!(state.new_artist_from_url(artist_url("Ghost", "https://GHOST.bandcamp.com/")).is_err());
+        assert_eq!(state.artists.len(), 2);
+        assert_eq!(state.artists_by_name("ghost").len(), 2);
+        assert!(state.artist(&artist_url("Ghost", "https://ghostband.bandcamp.com")).is_some());
+    }
+
+    #[test]
+    fn loading_a_state_keyed_by_name_rekeys_it_by_url() {
+        let serialized = r#"{
+            "artists": {
+                "Ghost": {
+                    "name": "Ghost",
+                    "url": "https://ghost.bandcamp.com/",
+                    "discography": { "albums": [], "eps": [], "singles": [] },
+                    "last_scrape_completed_on": "1970-01-01T00:00:00Z"
+                }
+            },
+            "next_artist_number": 1
+        }"#;
+
+        let state: ScraperState = serde_json::from_str(serialized).unwrap();
+
+        assert!(state.artists.contains_key("https://ghost.bandcamp.com"));
+        assert_eq!(state.artists_by_name("Ghost").len(), 1);
+        assert!(!state.artist_index_completed);
+    }
+
+    #[test]
+    fn resolved_custom_domains_move_to_their_subdomain() {
+        let mut state = ScraperState::new();
+        let custom_domain = artist_url("Ghost", "https://music.ghost.com");
+        let resolved = ArtistUrl {
+            band_id: Some(1234),
+            ..artist_url("Ghost", "https://ghost.bandcamp.com")
+        };
+        state.new_artist_from_url(custom_domain.clone()).unwrap();
+
+        assert_eq!(state.move_artist(&custom_domain, &resolved).url, resolved.url);
+        state.update_artist_discography(&resolved, ArtistDiscography::default());
+
+        assert_eq!(state.artists.len(), 1);
+        assert!(state.unscraped_artists().is_empty());
+        assert_eq!(state.artist_by_band_id(1234).unwrap().url, "https://ghost.bandcamp.com");
+        assert_eq!(state.artists_by_name("Ghost").len(), 1);
+
+        // Found through its custom domain again, e.g. on a later page of the index.
+        state.new_artist_from_url(custom_domain.clone()).unwrap();
+        state.move_artist(&custom_domain, &resolved);
+        assert_eq!(state.artists.len(), 1);
+        assert!(state.artists["https://ghost.bandcamp.com"].has_been_scraped());
+    }
+
+    #[test]
+    fn resolved_custom_domains_dont_replace_a_different_artist() {
+        let mut state = ScraperState::new();
+        let custom_domain = artist_url("Ghost", "https://music.ghost.com");
+        let other_artist = artist_url("Label", "https://label.bandcamp.com");
+        let resolved = ArtistUrl {
+            band_id: Some(1234),
+            ..artist_url("Ghost", "https://label.bandcamp.com")
+        };
+        state.new_artist_from_url(custom_domain.clone()).unwrap();
+        state.new_artist_from_url(other_artist.clone()).unwrap();
+
+        let stored_under = state.move_artist(&custom_domain, &resolved);
+        state.update_artist_discography(&stored_under, ArtistDiscography::default());
+
+        assert_eq!(stored_under.url, "https://music.ghost.com");
+        assert_eq!(state.artists.len(), 2);
+        assert_eq!(state.artist_by_band_id(1234).unwrap().url, "https://music.ghost.com");
+        assert!(state.artists["https://music.ghost.com"].has_been_scraped());
+        assert!(!state.artists["https://label.bandcamp.com"].has_been_scraped());
+    }
+
+    #[test]
+    fn custom_domains_that_only_differ_by_scheme_are_merged_when_loading() {
+        let serialized = r#"{
+            "artists": {
+                "http://music.ghost.com": {
+                    "name": "Ghost",
+                    "url": "http://music.ghost.com",
+                    "discography": { "albums": [], "eps": [], "singles": [] },
+                    "last_scrape_completed_on": "2022-03-11T00:00:00Z"
+                },
+                "https://music.ghost.com": {
+                    "name": "Ghost",
+                    "url": "https://music.ghost.com",
+                    "discography": { "albums": [], "eps": [], "singles": [] },
+                    "last_scrape_completed_on": "1970-01-01T00:00:00Z"
+                }
+            },
+            "next_artist_number": 2
+        }"#;
+
+        let state: ScraperState = serde_json::from_str(serialized).unwrap();
+
+        assert_eq!(state.artists.len(), 1);
+        assert!(state.artists["https://music.ghost.com"].has_been_scraped());
+        assert_eq!(state.artists_by_name("Ghost").len(), 1);
+    }
+
+    #[test]
+    fn releases_stored_as_the_old_enum_still_load() {
+        let serialized = r#"{
+            "albums": [{ "Album": { "name": "Meliora", "tracks": [{ "index": 1, "name": "Spirit", "duration": "05:15" }] } }],
+            "eps": [{ "EP": { "name": "If You Have Ghost", "tracks": [] } }],
+            "singles": []
+        }"#;
+
+        let discography: ArtistDiscography = serde_json::from_str(serialized).unwrap();
+
+        assert_eq!(discography.albums[0].kind, ReleaseKind::Album);
+        assert_eq!(discography.albums[0].name, "Meliora");
+        assert_eq!(discography.albums[0].tracks[0].name, "Spirit");
+        assert_eq!(discography.albums[0].tracks[0].duration, Some(Duration::from_secs(315)));
+        assert_eq!(discography.eps[0].kind, ReleaseKind::EP);
+
+        let reserialized = serde_json::to_string(&discography).unwrap();
+        assert!(reserialized.contains(r#""duration":315.0"#));
+        let discography: ArtistDiscography = serde_json::from_str(&reserialized).unwrap();
+        assert_eq!(discography.albums[0].name, "Meliora");
+        assert_eq!(discography.albums[0].runtime(), Duration::from_secs(315));
+    }
 }
@@ -0,0 +1,193 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::time::{sleep, Instant};
+use tracing::{debug, warn};
+
+// What an invalid rate (zero, negative or NaN) gets replaced with, so a bad setting slows the crawl down to a crawl instead of panicking.
+const MIN_REQUESTS_PER_SECOND: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    // How many requests can go out back to back before we start waiting.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        // Also catches NaN.
+        let requests_per_second = if requests_per_second > 0.0 {
+            requests_per_second
+        } else {
+            warn!(requests_per_second, min_requests_per_second = MIN_REQUESTS_PER_SECOND, "Invalid rate limit, using the minimum instead.");
+            MIN_REQUESTS_PER_SECOND
+        };
+
+        Self {
+            requests_per_second,
+            burst: burst.max(1),
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new(2.0, 4)
+    }
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        // The fields are public, so the limit might not have gone through `RateLimit::new`.
+        let limit = RateLimit::new(limit.requests_per_second, limit.burst);
+
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Takes a token right away and returns how long the caller has to wait before using it. Tokens can go negative, which is how later callers end up queued behind earlier ones.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.requests_per_second).min(self.limit.burst as f64);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.limit.requests_per_second)
+        }
+    }
+}
+
+pub(crate) struct RateLimiter {
+    global: Mutex<TokenBucket>,
+    default_host_limit: Option<RateLimit>,
+    host_limits: HashMap<String, RateLimit>,
+    host_buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(global_limit: RateLimit, default_host_limit: Option<RateLimit>, host_limits: HashMap<String, RateLimit>) -> Self {
+        Self {
+            global: Mutex::new(TokenBucket::new(global_limit)),
+            default_host_limit,
+            host_limits,
+            host_buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Waits until a request to `host` fits both in the global budget and in the host's budget.
+    pub(crate) async fn acquire(&self, host: Option<&str>) {
+        let now = Instant::now();
+        let global_wait = self.global.lock().unwrap().reserve(now);
+
+        let host_wait = host.and_then(|host| {
+            let limit = self.host_limits.get(host).copied().or(self.default_host_limit)?;
+            let mut host_buckets = self.host_buckets.lock().unwrap();
+
+            Some(host_buckets.entry(host.to_owned()).or_insert_with(|| TokenBucket::new(limit)).reserve(now))
+        }).unwrap_or_default();
+
+        let wait = global_wait.max(host_wait);
+
+        if !wait.is_zero() {
+            debug!(?host, ?wait, "Waiting for the rate limiter before sending a request.");
+            sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use tokio::time::{advance, Instant};
+
+    use super::{RateLimit, RateLimiter};
+
+    // The paused clock only fires timers on whole milliseconds.
+    fn assert_waited(start: Instant, expected: Duration) {
+        let waited = start.elapsed();
+        assert!(waited >= expected && waited <= expected + Duration::from_millis(1), "waited {:?} instead of {:?}", waited, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lets_a_burst_through_then_refills() {
+        let limiter = RateLimiter::new(RateLimit::new(2.0, 4), None, HashMap::new());
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(Some("bandcamp.com")).await;
+        }
+        assert_waited(start, Duration::ZERO);
+
+        let start = Instant::now();
+        limiter.acquire(Some("bandcamp.com")).await;
+        assert_waited(start, Duration::from_millis(500));
+
+        // Two tokens come back every second, but never more than the burst.
+        advance(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(None).await;
+        }
+        assert_waited(start, Duration::ZERO);
+
+        let start = Instant::now();
+        limiter.acquire(None).await;
+        limiter.acquire(None).await;
+        assert_waited(start, Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_each_host_separately() {
+        let host_limits = HashMap::from([("bandcamp.com".to_owned(), RateLimit::new(10.0, 1))]);
+        let limiter = RateLimiter::new(RateLimit::new(1000.0, 1000), Some(RateLimit::new(1.0, 1)), host_limits);
+
+        let start = Instant::now();
+        limiter.acquire(Some("ghost.bandcamp.com")).await;
+        limiter.acquire(Some("opeth.bandcamp.com")).await;
+        assert_waited(start, Duration::ZERO);
+
+        let start = Instant::now();
+        limiter.acquire(Some("ghost.bandcamp.com")).await;
+        assert_waited(start, Duration::from_secs(1));
+
+        // The override is faster than the default.
+        let start = Instant::now();
+        limiter.acquire(Some("bandcamp.com")).await;
+        limiter.acquire(Some("bandcamp.com")).await;
+        assert_waited(start, Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn global_limit_applies_across_hosts() {
+        let limiter = RateLimiter::new(RateLimit::new(1.0, 1), None, HashMap::new());
+
+        let start = Instant::now();
+        limiter.acquire(Some("ghost.bandcamp.com")).await;
+        limiter.acquire(Some("opeth.bandcamp.com")).await;
+        assert_waited(start, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn invalid_rates_are_clamped() {
+        for requests_per_second in [0.0, -1.0, f64::NAN] {
+            let limit = RateLimit::new(requests_per_second, 0);
+
+            assert!(limit.requests_per_second > 0.0);
+            assert_eq!(limit.burst, 1);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashMap, fs::{self, File}, io::{BufRead, BufReader, Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+
+use async_trait::async_trait;
+use reqwest::{StatusCode, header::{HeaderMap, HeaderName, HeaderValue}};
+use tracing::{debug, info, warn};
+
+use crate::{cache::{CachePolicy, HttpCache}, error::{Error, Result}, fetch::{FetchRequest, FetchResponse, Fetcher}};
+
+// What to do when asked for a page that was never archived.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayMiss {
+    // Fail the request with `Error::NotArchived`.
+    Fail,
+    // Answer with a 404, so the page is treated like one that doesn't exist.
+    Skip,
+}
+
+// Where a response record's HTTP block is in a WARC file.
+#[derive(Debug)]
+struct WarcRecordLocation {
+    path: PathBuf,
+    offset: u64,
+    length: u64,
+}
+
+enum ReplaySource {
+    // Keyed by the target uri of each response record.
+    Warc(HashMap<String, WarcRecordLocation>),
+    Cache(HttpCache),
+}
+
+// Answers requests from a previous crawl without touching the network, so parsers can be run again over pages we already have. Requests still go through the scraper's rate limiter, so pair this with a high `requests_per_second`.
+pub struct ReplayFetcher {
+    source: ReplaySource,
+    on_miss: ReplayMiss,
+}
+
+impl ReplayFetcher {
+    // Indexes every `.warc` file in `dir`, as written by `BcScraperBuilder::warc`. Compressed files and chunked bodies aren't supported. When a url was archived more than once, the last successful response wins.
+    pub fn from_warc_dir(dir: impl AsRef<Path>, on_miss: ReplayMiss) -> Result<Self> {
+        let dir = dir.as_ref();
+        let io_error = |source| Error::Io { path: dir.to_owned(), source };
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().is_some_and(|e| e == "warc") {
+                paths.push(path);
+            }
+        }
+        // File names start with the time the crawl started, so this is also the order they were written in.
+        paths.sort();
+
+        let mut records = HashMap::new();
+        for path in &paths {
+            index_warc_file(path, &mut records)?;
+        }
+
+        info!(dir = %dir.display(), files = paths.len(), responses = records.len(), "Indexed the WARC files for replay.");
+
+        Ok(Self {
+            source: ReplaySource::Warc(records.into_iter().map(|(url, (location, _))| (url, location)).collect()),
+            on_miss,
+        })
+    }
+
+    // Serves whatever is in a cache directory written by `BcScraperBuilder::cache`, no matter how old.
+    pub fn from_cache_dir(dir: impl Into<PathBuf>, on_miss: ReplayMiss) -> Self {
+        Self {
+            source: ReplaySource::Cache(HttpCache::new(CachePolicy::new(dir))),
+            on_miss,
+        }
+    }
+
+    fn miss(&self, request: FetchRequest) -> Result<FetchResponse> {
+        debug!(url = %request.url, on_miss = ?self.on_miss, "The page isn't in the archive.");
+
+        match self.on_miss {
+            ReplayMiss::Fail => Err(Error::NotArchived { url: request.url.to_string() }),
+            ReplayMiss::Skip => Ok(FetchResponse {
+                url: request.url,
+                status: StatusCode::NOT_FOUND,
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReplayFetcher {
+    async fn fetch(&self, request: FetchRequest) -> Result<FetchResponse> {
+        match &self.source {
+            ReplaySource::Warc(records) => match records.get(request.url.as_str()) {
+                Some(location) => read_http_response(location, request),
+                None => self.miss(request),
+            },
+            ReplaySource::Cache(cache) => match cache.lookup(&request.url).await {
+                Some(cached) => Ok(cached.response),
+                None => self.miss(request),
+            },
+        }
+    }
+}
+
+// Adds the response records in `path` to `records`, along with whether each one was successful.
+fn index_warc_file(path: &Path, records: &mut HashMap<String, (WarcRecordLocation, bool)>) -> Result<()> {
+    let io_error = |source| Error::Io { path: path.to_owned(), source };
+    let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+    let mut offset = 0;
+
+    loop {
+        let mut fields = HashMap::new();
+        let mut line = String::new();
+
+        // Skips the blank lines between records, then reads the header up to the next blank line.
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).map_err(io_error)?;
+            offset += read as u64;
+
+            if read == 0 {
+                return Ok(());
+            }
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                if fields.is_empty() {
+                    continue;
+                }
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                fields.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+            }
+        }
+
+        let length: u64 = fields.get("content-length").and_then(|l| l.parse().ok()).ok_or_else(|| Error::Io {
+            path: path.to_owned(),
+            source: std::io::Error::new(std::io::ErrorKind::InvalidData, format!("WARC record at offset {} has no valid Content-Length", offset)),
+        })?;
+
+        if let (Some("response"), Some(url)) = (fields.get("warc-type").map(String::as_str), fields.get("warc-target-uri")) {
+            let location = WarcRecordLocation {
+                path: path.to_owned(),
+                offset,
+                length,
+            };
+            let is_success = read_status(&location).is_ok_and(|s| s.is_success());
+
+            // Keep a failed response around only until we see a successful one.
+            if is_success || records.get(url).is_none_or(|(_, was_success)| !was_success) {
+                records.insert(url.clone(), (location, is_success));
+            }
+        }
+
+        reader.seek_relative(length as i64).map_err(io_error)?;
+        offset += length;
+    }
+}
+
+fn read_block(location: &WarcRecordLocation) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(&location.path)?;
+    file.seek(SeekFrom::Start(location.offset))?;
+
+    let mut block = vec![0; location.length as usize];
+    file.read_exact(&mut block)?;
+    Ok(block)
+}
+
+fn read_status(location: &WarcRecordLocation) -> std::io::Result<StatusCode> {
+    let mut file = File::open(&location.path)?;
+    file.seek(SeekFrom::Start(location.offset))?;
+
+    let mut status_line = String::new();
+    BufReader::new(file.take(location.length)).read_line(&mut status_line)?;
+
+    parse_status_line(&status_line).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid status line"))
+}
+
+fn parse_status_line(status_line: &str) -> Option<StatusCode> {
+    let status = status_line.split_whitespace().nth(1)?;
+    StatusCode::from_bytes(status.as_bytes()).ok()
+}
+
+fn read_http_response(location: &WarcRecordLocation, request: FetchRequest) -> Result<FetchResponse> {
+    let block = read_block(location).map_err(|source| Error::Io { path: location.path.clone(), source })?;
+    let invalid_record = || Error::Io {
+        path: location.path.clone(),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid HTTP response in the WARC record at offset {}", location.offset)),
+    };
+
+    let header_end = block.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(invalid_record)?;
+    let head = String::from_utf8_lossy(&block[..header_end]);
+    let mut lines = head.split("\r\n");
+    let status = lines.next().and_then(parse_status_line).ok_or_else(invalid_record)?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        match line.split_once(':').and_then(|(name, value)| Some((HeaderName::from_bytes(name.trim().as_bytes()).ok()?, HeaderValue::from_str(value.trim()).ok()?))) {
+            Some((name, value)) => {
+                headers.append(name, value);
+            }
+            None => warn!(url = %request.url, line, "Skipping an invalid header in an archived response."),
+        }
+    }
+
+    Ok(FetchResponse {
+        url: request.url,
+        status,
+        headers,
+        body: block[header_end + 4..].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use reqwest::{StatusCode, Url, header::{HeaderMap, HeaderValue, ETAG}};
+
+    use super::{ReplayFetcher, ReplayMiss};
+    use crate::{error::Error, fetch::{FetchRequest, FetchResponse, Fetcher}, warc::{WarcPolicy, WarcWriter}};
+
+    fn response(url: &Url, status: StatusCode, body: &str) -> FetchResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+
+        FetchResponse {
+            url: url.clone(),
+            status,
+            headers,
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_archived_responses() {
+        let dir = std::env::temp_dir().join(format!("bcz-replay-test-{}", std::process::id()));
+        let writer = WarcWriter::new(WarcPolicy {
+            max_file_size: 512,
+            ..WarcPolicy::new(&dir)
+        });
+        let music_url = Url::parse("https://ghost.bandcamp.com/music").unwrap();
+        let album_url = Url::parse("https://ghost.bandcamp.com/album/meliora").unwrap();
+        let missing_url = Url::parse("https://ghost.bandcamp.com/album/impera").unwrap();
+
+        writer.write_exchange(&FetchRequest::get(music_url.clone()), &response(&music_url, StatusCode::OK, "<html>music</html>")).unwrap();
+        writer.write_exchange(&FetchRequest::get(album_url.clone()), &response(&album_url, StatusCode::OK, "<html>\r\n\r\nmeliora</html>")).unwrap();
+        // Comes later, but shouldn't replace the successful one.
+        writer.write_exchange(&FetchRequest::get(album_url.clone()), &response(&album_url, StatusCode::SERVICE_UNAVAILABLE, "")).unwrap();
+
+        let fetcher = ReplayFetcher::from_warc_dir(&dir, ReplayMiss::Fail).unwrap();
+
+        let music = fetcher.fetch(FetchRequest::get(music_url)).await.unwrap();
+        assert_eq!(music.status, StatusCode::OK);
+        assert_eq!(music.headers[ETAG], "\"v1\"");
+        assert_eq!(music.text(), "<html>music</html>");
+
+        let album = fetcher.fetch(FetchRequest::get(album_url)).await.unwrap();
+        assert_eq!(album.status, StatusCode::OK);
+        assert_eq!(album.text(), "<html>\r\n\r\nmeliora</html>");
+
+        assert!(matches!(fetcher.fetch(FetchRequest::get(missing_url.clone())).await, Err(Error::NotArchived { .. })));
+
+        let fetcher = ReplayFetcher::from_warc_dir(&dir, ReplayMiss::Skip).unwrap();
+        assert_eq!(fetcher.fetch(FetchRequest::get(missing_url)).await.unwrap().status, StatusCode::NOT_FOUND);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
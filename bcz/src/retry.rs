@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::error::Error;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    // Includes the first attempt, so 1 means no retries at all.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    // Longest `Retry-After` we're willing to wait for. Longer ones get cut down to this.
+    pub max_retry_after: Duration,
+    // Fraction of the backoff that gets randomized, between 0 and 1. Keeps concurrent fetches from retrying in lockstep.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_retry_after: Duration::from_secs(5 * 60),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    // How long to wait after the given failed attempt (starting at 1), without looking at what the server asked for. Always between zero and `max_backoff`, even with odd settings like a negative multiplier.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let max_backoff = self.max_backoff.as_secs_f64();
+        let backoff = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent)).min(max_backoff);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let backoff = if jitter > 0.0 {
+            backoff * (1.0 - jitter * rand::thread_rng().gen::<f64>())
+        } else {
+            backoff
+        };
+
+        // Huge durations don't survive the trip through f64, so they can round up past `max_backoff`.
+        Duration::try_from_secs_f64(backoff.max(0.0)).map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    // Returns how long to wait before trying again, or `None` if we shouldn't retry after this error. A `Retry-After` from the server wins over our own backoff, up to `max_retry_after`.
+    pub(crate) fn delay_after(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if attempt >= self.max_attempts || !error.is_retryable() {
+            return None;
+        }
+
+        Some(error.retry_after().map(|retry_after| retry_after.min(self.max_retry_after)).unwrap_or_else(|| self.backoff(attempt)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+    use crate::error::Error;
+
+    fn policy_without_jitter() -> RetryPolicy {
+        RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_max() {
+        let policy = policy_without_jitter();
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(20), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn odd_settings_dont_make_backoff_panic() {
+        let negative = RetryPolicy {
+            multiplier: -2.0,
+            ..policy_without_jitter()
+        };
+        assert_eq!(negative.backoff(1), Duration::from_millis(500));
+        assert_eq!(negative.backoff(2), Duration::ZERO);
+
+        let not_a_number = RetryPolicy {
+            multiplier: f64::NAN,
+            ..policy_without_jitter()
+        };
+        assert_eq!(not_a_number.backoff(2), not_a_number.max_backoff);
+
+        let unbounded = RetryPolicy {
+            multiplier: f64::MAX,
+            max_backoff: Duration::MAX,
+            ..policy_without_jitter()
+        };
+        assert_eq!(unbounded.backoff(1), Duration::from_millis(500));
+        assert_eq!(unbounded.backoff(3), Duration::MAX);
+        assert_eq!(unbounded.backoff(u32::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn retry_after_wins_over_backoff() {
+        let policy = policy_without_jitter();
+        let error = Error::RateLimited {
+            url: "https://bandcamp.com/artist_index".to_owned(),
+            retry_after: Some(Duration::from_secs(7)),
+        };
+
+        assert_eq!(policy.delay_after(1, &error), Some(Duration::from_secs(7)));
+        assert_eq!(policy.delay_after(policy.max_attempts, &error), None);
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let policy = policy_without_jitter();
+        let error = Error::Unavailable {
+            url: "https://bandcamp.com/artist_index".to_owned(),
+            retry_after: Some(Duration::from_secs(24 * 60 * 60)),
+        };
+
+        assert_eq!(policy.delay_after(1, &error), Some(policy.max_retry_after));
+    }
+
+    #[test]
+    fn doesnt_retry_permanent_errors() {
+        let policy = policy_without_jitter();
+        let error = Error::NotFound {
+            url: "https://example.bandcamp.com/music".to_owned(),
+        };
+
+        assert_eq!(policy.delay_after(1, &error), None);
+    }
+}
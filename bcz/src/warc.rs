@@ -0,0 +1,223 @@
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::PathBuf, sync::{Mutex, atomic::{AtomicU32, Ordering}}};
+
+use chrono::Utc;
+use rand::Rng;
+use tracing::{debug, info};
+
+use crate::{error::{Error, Result}, fetch::{FetchRequest, FetchResponse}};
+
+#[derive(Clone, Debug)]
+pub struct WarcPolicy {
+    pub dir: PathBuf,
+    // Files are named `<prefix>-<timestamp>-<number>.warc`.
+    pub prefix: String,
+    // A new file is started once the current one would grow past this. A single record bigger than this still gets a file of its own.
+    pub max_file_size: u64,
+}
+
+impl WarcPolicy {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "bcz".to_owned(),
+            max_file_size: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+struct WarcFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+    exchanges: usize,
+}
+
+// Appends every request/response pair that went over the network to WARC 1.1 files, so a crawl can be parsed again later. Pages served from the cache aren't written again.
+pub(crate) struct WarcWriter {
+    policy: WarcPolicy,
+    // Used in file names, so files from different runs don't clash.
+    started_at: String,
+    current: Mutex<Option<WarcFile>>,
+    files_started: AtomicU32,
+}
+
+impl WarcWriter {
+    pub(crate) fn new(policy: WarcPolicy) -> Self {
+        Self {
+            policy,
+            started_at: Utc::now().format("%Y%m%d%H%M%S").to_string(),
+            current: Mutex::new(None),
+            files_started: AtomicU32::new(0),
+        }
+    }
+
+    // Blocks on the file, so it belongs on a blocking thread when called from async code.
+    pub(crate) fn write_exchange(&self, request: &FetchRequest, response: &FetchResponse) -> Result<()> {
+        let response_id = record_id();
+        let request_id = record_id();
+        let target_uri = request.url.as_str();
+
+        let response_record = record(&[
+            ("WARC-Type", "response"),
+            ("WARC-Record-ID", &response_id),
+            ("WARC-Target-URI", target_uri),
+            ("Content-Type", "application/http;msgtype=response"),
+        ], &response_block(response));
+        let request_record = record(&[
+            ("WARC-Type", "request"),
+            ("WARC-Record-ID", &request_id),
+            ("WARC-Target-URI", target_uri),
+            ("WARC-Concurrent-To", &response_id),
+            ("Content-Type", "application/http;msgtype=request"),
+        ], &request_block(request));
+
+        let mut current = self.current.lock().unwrap();
+        let size = (response_record.len() + request_record.len()) as u64;
+
+        if current.as_ref().is_none_or(|file| file.exchanges > 0 && file.size + size > self.policy.max_file_size) {
+            if let Some(mut file) = current.take() {
+                file.writer.flush().map_err(|source| Error::Io { path: file.path.clone(), source })?;
+                info!(path = %file.path.display(), size = file.size, "Finished a WARC file.");
+            }
+
+            *current = Some(self.new_file()?);
+        }
+
+        let file = current.as_mut().unwrap();
+        let io_error = |source| Error::Io { path: file.path.clone(), source };
+
+        file.writer.write_all(&request_record).map_err(io_error)?;
+        file.writer.write_all(&response_record).map_err(io_error)?;
+        // Keeps what's on disk usable if we get killed.
+        file.writer.flush().map_err(io_error)?;
+        file.size += size;
+        file.exchanges += 1;
+
+        debug!(url = target_uri, path = %file.path.display(), "Archived the response.");
+        Ok(())
+    }
+
+    fn new_file(&self) -> Result<WarcFile> {
+        fs::create_dir_all(&self.policy.dir).map_err(|source| Error::Io { path: self.policy.dir.clone(), source })?;
+
+        let file_number = self.files_started.fetch_add(1, Ordering::Relaxed) + 1;
+        let path = self.policy.dir.join(format!("{}-{}-{:05}.warc", self.policy.prefix, self.started_at, file_number));
+        let file = File::options().create_new(true).write(true).open(&path).map_err(|source| Error::Io { path: path.clone(), source })?;
+        let mut writer = BufWriter::new(file);
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let warcinfo = record(&[
+            ("WARC-Type", "warcinfo"),
+            ("WARC-Record-ID", &record_id()),
+            ("WARC-Filename", &file_name),
+            ("Content-Type", "application/warc-fields"),
+        ], format!("software: bcz/{}\r\nformat: WARC File Format 1.1\r\n", env!("CARGO_PKG_VERSION")).as_bytes());
+        writer.write_all(&warcinfo).map_err(|source| Error::Io { path: path.clone(), source })?;
+
+        info!(path = %path.display(), "Started a new WARC file.");
+
+        Ok(WarcFile {
+            path,
+            writer,
+            size: warcinfo.len() as u64,
+            exchanges: 0,
+        })
+    }
+}
+
+fn record(fields: &[(&str, &str)], block: &[u8]) -> Vec<u8> {
+    let mut record = format!("WARC/1.1\r\nWARC-Date: {}\r\n", Utc::now().format("%Y-%m-%dT%H:%M:%SZ"));
+
+    for (name, value) in fields {
+        record.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    record.push_str(&format!("Content-Length: {}\r\n\r\n", block.len()));
+
+    let mut record = record.into_bytes();
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+    record
+}
+
+fn request_block(request: &FetchRequest) -> Vec<u8> {
+    let path = match request.url.query() {
+        Some(query) => format!("{}?{}", request.url.path(), query),
+        None => request.url.path().to_owned(),
+    };
+    let mut block = format!("GET {} HTTP/1.1\r\nHost: {}\r\n", path, request.url.host_str().unwrap_or_default());
+
+    for (name, value) in &request.headers {
+        block.push_str(&format!("{}: {}\r\n", name, String::from_utf8_lossy(value.as_bytes())));
+    }
+    block.push_str("\r\n");
+
+    block.into_bytes()
+}
+
+// The body is the one we got after decompressing, so any `Content-Encoding` the server sent doesn't apply to it anymore.
+fn response_block(response: &FetchResponse) -> Vec<u8> {
+    let mut block = format!("HTTP/1.1 {} {}\r\n", response.status.as_u16(), response.status.canonical_reason().unwrap_or_default());
+
+    for (name, value) in &response.headers {
+        block.push_str(&format!("{}: {}\r\n", name, String::from_utf8_lossy(value.as_bytes())));
+    }
+    block.push_str("\r\n");
+
+    let mut block = block.into_bytes();
+    block.extend_from_slice(&response.body);
+    block
+}
+
+// A random (version 4) UUID, which is what WARC records are usually identified by.
+fn record_id() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("<urn:uuid:{}-{}-{}-{}-{}>", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use reqwest::{StatusCode, Url, header::HeaderMap};
+
+    use super::{WarcPolicy, WarcWriter};
+    use crate::fetch::{FetchRequest, FetchResponse};
+
+    #[test]
+    fn writes_exchanges_and_rotates_files() {
+        let dir = std::env::temp_dir().join(format!("bcz-warc-test-{}", std::process::id()));
+        let writer = WarcWriter::new(WarcPolicy {
+            max_file_size: 1024,
+            ..WarcPolicy::new(&dir)
+        });
+        let url = Url::parse("https://bandcamp.com/artist_index?sort_asc=1&page=1").unwrap();
+        let response = FetchResponse {
+            url: url.clone(),
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: "<html>".repeat(100).into_bytes(),
+        };
+
+        writer.write_exchange(&FetchRequest::get(url.clone()), &response).unwrap();
+        writer.write_exchange(&FetchRequest::get(url), &response).unwrap();
+
+        let mut files: Vec<_> = fs::read_dir(&dir).unwrap().map(|f| f.unwrap().path()).collect();
+        files.sort();
+        assert_eq!(files.len(), 2);
+
+        let contents = fs::read_to_string(&files[0]).unwrap();
+        assert!(contents.starts_with("WARC/1.1\r\n"));
+        assert_eq!(contents.matches("WARC-Type: warcinfo").count(), 1);
+        assert!(contents.contains("WARC-Type: request"));
+        assert!(contents.contains("GET /artist_index?sort_asc=1&page=1 HTTP/1.1\r\nHost: bandcamp.com\r\n"));
+        assert!(contents.contains("WARC-Target-URI: https://bandcamp.com/artist_index?sort_asc=1&page=1\r\n"));
+        assert!(contents.contains("HTTP/1.1 200 OK\r\n\r\n<html><html>"));
+        assert!(contents.contains(&format!("Content-Length: {}\r\n", "HTTP/1.1 200 OK\r\n\r\n".len() + 600)));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,723 @@
+// End-to-end tests against a local server pretending to be Bandcamp.
+use std::{collections::{HashMap, VecDeque}, net::SocketAddr, path::PathBuf, sync::{Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use bcz::{bc::{ArtistUrl, BcScraper, CrawlLimits}, fetch::{FetchRequest, FetchResponse, Fetcher, ReplayFetcher, ReplayMiss, ReqwestFetcher}, CachePolicy, RetryPolicy, ScraperState, Url, WarcPolicy};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}, sync::Notify};
+use tokio_stream::StreamExt;
+
+const ARTISTS_PER_PAGE: usize = 10;
+const DISCOGRAPHY_ETAG: &str = "\"music-v1\"";
+// The discography fixture links to a release on another host, which the mock can't serve.
+const DISCOGRAPHY_PAGE: &str = r#"<html><body><ol id="music-grid">
+<li class="music-grid-item"><a href="/album/opus-eponymous"><p class="title">Opus Eponymous</p></a></li>
+<li class="music-grid-item"><a href="/track/secular-haze"><p class="title">Secular Haze</p></a></li>
+</ol></body></html>"#;
+
+struct MockResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    fn ok(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockBandcampState {
+    total_artists: usize,
+    // Artist pages say the artist lives on `artist-<n>.bandcamp.com`, like a custom domain page would.
+    custom_domains: bool,
+    requests: Vec<String>,
+    // Requests that came with an `If-None-Match`.
+    conditional_requests: Vec<String>,
+    // Responses to send instead of the real page for the next requests to a path.
+    failures: HashMap<String, VecDeque<MockResponse>>,
+    // How long every response takes, so concurrent requests overlap.
+    delay: Duration,
+    // Requests being answered right now, and the most there ever were, by crawl stage (see `stage`).
+    in_flight: HashMap<&'static str, usize>,
+    max_in_flight: HashMap<&'static str, usize>,
+}
+
+// Artist pages are fetched one after the other for each artist, so there's never more of them in flight than artists being scraped.
+fn stage(path: &str) -> Option<&'static str> {
+    if path.starts_with("/artists/") || path == "/music" {
+        Some("artist")
+    } else if path.starts_with("/album/") || path.starts_with("/track/") {
+        Some("release")
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+struct MockBandcamp {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockBandcampState>>,
+}
+
+impl MockBandcamp {
+    async fn start(total_artists: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            addr: listener.local_addr().unwrap(),
+            state: Arc::new(Mutex::new(MockBandcampState {
+                total_artists,
+                ..Default::default()
+            })),
+        };
+
+        let handler = server.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move { handler.handle_connection(stream).await });
+            }
+        });
+
+        server
+    }
+
+    fn base_url(&self) -> Url {
+        Url::parse(&format!("http://{}", self.addr)).unwrap()
+    }
+
+    fn artist_url(&self, n: usize) -> String {
+        format!("http://{}/artists/{}", self.addr, n)
+    }
+
+    fn fail_next(&self, path: &str, response: MockResponse) {
+        self.state.lock().unwrap().failures.entry(path.to_owned()).or_default().push_back(response);
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    fn conditional_requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().conditional_requests.clone()
+    }
+
+    fn max_in_flight(&self, stage: &str) -> usize {
+        self.state.lock().unwrap().max_in_flight.get(stage).copied().unwrap_or_default()
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let target = request.split_whitespace().nth(1).unwrap_or("/").to_owned();
+        let if_none_match = request.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("if-none-match"))
+            .map(|(_, value)| value.trim().to_owned());
+        let stage = stage(target.split('?').next().unwrap_or_default());
+        let delay = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(stage) = stage {
+                let in_flight = state.in_flight.entry(stage).or_default();
+                *in_flight += 1;
+                let in_flight = *in_flight;
+                let max_in_flight = state.max_in_flight.entry(stage).or_default();
+                *max_in_flight = (*max_in_flight).max(in_flight);
+            }
+            state.delay
+        };
+        tokio::time::sleep(delay).await;
+        let response = self.respond(&target, if_none_match.as_deref());
+        // Before the response goes out, so the client can't start its next request while this one still counts.
+        if let Some(stage) = stage {
+            *self.state.lock().unwrap().in_flight.get_mut(stage).unwrap() -= 1;
+        }
+
+        let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+        for (name, value) in response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        let _ = stream.write_all(head.as_bytes()).await;
+        let _ = stream.write_all(response.body.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+
+    fn respond(&self, target: &str, if_none_match: Option<&str>) -> MockResponse {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(target.to_owned());
+        if if_none_match.is_some() {
+            state.conditional_requests.push(target.to_owned());
+        }
+
+        if let Some(failure) = state.failures.get_mut(path).and_then(|f| f.pop_front()) {
+            return failure;
+        }
+
+        match path {
+            "/artist_index" | "/mirror/artist_index" => {
+                let page = query.split('&')
+                    .find_map(|p| p.strip_prefix("page="))
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or(1);
+                MockResponse::ok(self.artist_index_page(page, state.total_artists))
+            }
+            p if p.starts_with("/artists/") && state.custom_domains => {
+                let n: usize = p["/artists/".len()..].parse().unwrap();
+                MockResponse::ok(format!(
+                    r#"<html><body><div id="pagedata" data-band="{{&quot;id&quot;:{},&quot;url&quot;:&quot;https://artist-{}.bandcamp.com&quot;}}"></div></body></html>"#,
+                    1000 + n,
+                    n,
+                ))
+            }
+            p if p.starts_with("/artists/") => MockResponse::ok("<html><body><h1>An artist</h1></body></html>"),
+            "/music" | "/mirror/music" if if_none_match == Some(DISCOGRAPHY_ETAG) => MockResponse {
+                status: 304,
+                headers: vec![("ETag", DISCOGRAPHY_ETAG.to_owned())],
+                body: String::new(),
+            },
+            "/music" | "/mirror/music" => MockResponse {
+                headers: vec![("ETag", DISCOGRAPHY_ETAG.to_owned())],
+                ..MockResponse::ok(DISCOGRAPHY_PAGE)
+            },
+            "/album/opus-eponymous" => MockResponse::ok(include_str!("fixtures/album.html")),
+            "/track/secular-haze" => MockResponse::ok(include_str!("fixtures/track.html")),
+            _ => MockResponse::status(404),
+        }
+    }
+
+    fn artist_index_page(&self, page: usize, total_artists: usize) -> String {
+        let first = (page - 1) * ARTISTS_PER_PAGE;
+        let last = (first + ARTISTS_PER_PAGE).min(total_artists);
+
+        let items: String = (first..last.max(first)).map(|n| format!(
+            r#"<li class="item"><a href="{}?from=artist_index"><div class="itemtext">Artist {}</div></a></li>"#,
+            self.artist_url(n),
+            n,
+        )).collect();
+        // Like Bandcamp, a full page always links to the next one, even when nothing is left.
+        let next = if last >= first + ARTISTS_PER_PAGE {
+            format!(r#"<a class="next" href="?page={}&amp;sort_asc=1">next</a>"#, page + 1)
+        } else {
+            String::new()
+        };
+
+        format!(r#"<html><body><ul class="item_list">{}</ul><div class="pager"><span class="pagenum cur">{}</span>{}</div></body></html>"#, items, page, next)
+    }
+}
+
+// Sends requests for `*.bandcamp.com` to the mock server, so artists on custom domains have a subdomain to resolve to.
+struct SubdomainsToMock {
+    inner: ReqwestFetcher,
+    addr: SocketAddr,
+}
+
+#[async_trait]
+impl Fetcher for SubdomainsToMock {
+    async fn fetch(&self, mut request: FetchRequest) -> bcz::Result<FetchResponse> {
+        if request.url.host_str().is_some_and(|host| host.ends_with(".bandcamp.com")) {
+            request.url.set_scheme("http").unwrap();
+            request.url.set_host(Some(&self.addr.ip().to_string())).unwrap();
+            request.url.set_port(Some(self.addr.port())).unwrap();
+        }
+
+        self.inner.fetch(request).await
+    }
+}
+
+// A fetcher that isn't built on reqwest, whose first requests fail before reaching the server.
+struct FlakyConnection {
+    inner: ReqwestFetcher,
+    failures_left: Mutex<usize>,
+}
+
+#[async_trait]
+impl Fetcher for FlakyConnection {
+    async fn fetch(&self, request: FetchRequest) -> bcz::Result<FetchResponse> {
+        {
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err(bcz::Error::transport(request.url.as_str(), std::io::Error::from(std::io::ErrorKind::ConnectionRefused)));
+            }
+        }
+
+        self.inner.fetch(request).await
+    }
+}
+
+// Pulls the cache out from under the scraper as soon as a page turns out not to be modified, like a disk failing between reading the cache and writing to it.
+struct LosesCacheOnNotModified {
+    inner: ReqwestFetcher,
+    cache_dir: PathBuf,
+}
+
+#[async_trait]
+impl Fetcher for LosesCacheOnNotModified {
+    async fn fetch(&self, request: FetchRequest) -> bcz::Result<FetchResponse> {
+        let response = self.inner.fetch(request).await?;
+
+        if response.status == reqwest::StatusCode::NOT_MODIFIED {
+            std::fs::remove_dir_all(&self.cache_dir).unwrap();
+        }
+
+        Ok(response)
+    }
+}
+
+fn scraper_for(server: &MockBandcamp, state: ScraperState) -> BcScraper {
+    BcScraper::builder()
+        .state(state)
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .burst(100)
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .build()
+}
+
+async fn collect_artist_names(scraper: &BcScraper) -> Vec<String> {
+    let artists: Vec<_> = scraper.artists().collect().await;
+    artists.into_iter().map(|a| a.unwrap().name).collect()
+}
+
+fn artist_names(range: std::ops::Range<usize>) -> Vec<String> {
+    range.map(|n| format!("Artist {}", n)).collect()
+}
+
+fn page_requests(server: &MockBandcamp) -> Vec<String> {
+    server.requests().into_iter()
+        .filter(|r| r.contains("artist_index"))
+        .map(|r| r.split("page=").nth(1).unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn walks_the_whole_artist_index() {
+    let server = MockBandcamp::start(23).await;
+    let scraper = scraper_for(&server, ScraperState::new());
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..23));
+    // The last page is short and has no next link, so we never ask for page 4.
+    assert_eq!(page_requests(&server), vec!["1", "2", "3"]);
+
+    let state = scraper.state();
+    assert_eq!(state.next_artist_number, 23);
+    assert_eq!(state.artists.len(), 23);
+    assert!(state.artist_index_completed);
+}
+
+#[tokio::test]
+async fn stops_at_an_empty_page() {
+    // Exactly two full pages, so the only way to find the end is an empty third page.
+    let server = MockBandcamp::start(20).await;
+    let scraper = scraper_for(&server, ScraperState::new());
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..20));
+    assert_eq!(page_requests(&server), vec!["1", "2", "3"]);
+    assert!(scraper.state().artist_index_completed);
+}
+
+#[tokio::test]
+async fn resumes_from_next_artist_number() {
+    let server = MockBandcamp::start(23).await;
+    let mut state = ScraperState::new();
+    state.next_artist_number = 15;
+    let scraper = scraper_for(&server, state);
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(15..23));
+    // Page 1 is only fetched to find out the page size.
+    assert_eq!(page_requests(&server), vec!["1", "2", "3"]);
+    assert_eq!(scraper.state().next_artist_number, 23);
+}
+
+#[tokio::test]
+async fn completed_index_isnt_walked_again() {
+    let server = MockBandcamp::start(23).await;
+    let mut state = ScraperState::new();
+    state.artist_index_completed = true;
+    let scraper = scraper_for(&server, state);
+
+    assert!(collect_artist_names(&scraper).await.is_empty());
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn retries_failed_index_pages() {
+    let server = MockBandcamp::start(23).await;
+    server.fail_next("/artist_index", MockResponse::status(500));
+    server.fail_next("/artist_index", MockResponse {
+        status: 503,
+        headers: vec![("Retry-After", "0".to_owned())],
+        body: String::new(),
+    });
+    // Runs out of client retries, so the stream itself has to back off and try again.
+    server.fail_next("/artist_index", MockResponse::status(429));
+    let scraper = scraper_for(&server, ScraperState::new());
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..23));
+    assert_eq!(page_requests(&server), vec!["1", "1", "1", "1", "2", "3"]);
+}
+
+#[tokio::test]
+async fn gives_up_on_an_index_that_keeps_failing() {
+    let server = MockBandcamp::start(23).await;
+    for _ in 0..3 {
+        server.fail_next("/artist_index", MockResponse::status(500));
+    }
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .retry_policy(RetryPolicy::no_retries())
+        .build();
+
+    let artists: Vec<_> = scraper.artists().collect().await;
+
+    assert_eq!(artists.len(), 1);
+    assert!(matches!(artists[0], Err(bcz::Error::UnexpectedStatus { status: 500, .. })));
+    assert_eq!(page_requests(&server), vec!["1"]);
+    assert!(!scraper.state().artist_index_completed);
+}
+
+#[tokio::test]
+async fn does_not_finish_the_index_on_a_page_it_does_not_recognize() {
+    let server = MockBandcamp::start(23).await;
+    server.fail_next("/artist_index", MockResponse::ok("<html><body><h1>One moment, please...</h1></body></html>"));
+    let scraper = scraper_for(&server, ScraperState::new());
+
+    let artists: Vec<_> = scraper.artists().collect().await;
+
+    assert_eq!(artists.len(), 1);
+    assert!(matches!(artists[0], Err(bcz::Error::Parse { .. })));
+    assert!(!scraper.state().artist_index_completed);
+}
+
+#[tokio::test]
+async fn retries_transport_errors_from_other_fetchers() {
+    let server = MockBandcamp::start(3).await;
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        })
+        .fetcher(FlakyConnection {
+            inner: ReqwestFetcher::new(reqwest::Client::new()),
+            failures_left: Mutex::new(2),
+        })
+        .build();
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..3));
+    assert_eq!(page_requests(&server), vec!["1"]);
+}
+
+#[tokio::test]
+async fn scrapes_an_artist_discography() {
+    let server = MockBandcamp::start(1).await;
+    let scraper = scraper_for(&server, ScraperState::new());
+    let artist_url = ArtistUrl {
+        name: "Artist 0".to_owned(),
+        url: server.artist_url(0),
+        band_id: None,
+    };
+
+    let discography = scraper.discography(&artist_url).await.unwrap();
+
+    assert_eq!(discography.albums.len(), 1);
+    assert_eq!(discography.singles.len(), 1);
+    let state = scraper.state();
+    let artist = state.artist(&artist_url).unwrap();
+    assert_eq!(artist.discography.albums.len(), 1);
+    assert!(artist.last_scrape_completed_on.timestamp() > 0);
+}
+
+#[tokio::test]
+async fn skips_releases_that_are_gone_but_not_ones_that_might_come_back() {
+    let server = MockBandcamp::start(1).await;
+    let artist_url = ArtistUrl {
+        name: "Artist 0".to_owned(),
+        url: server.artist_url(0),
+        band_id: None,
+    };
+
+    server.fail_next("/album/opus-eponymous", MockResponse::status(404));
+    let scraper = scraper_for(&server, ScraperState::new());
+    let discography = scraper.discography(&artist_url).await.unwrap();
+    assert_eq!(discography.albums.len(), 0);
+    assert_eq!(discography.singles.len(), 1);
+
+    // Still failing after every retry, so the artist is left for the next crawl.
+    for _ in 0..3 {
+        server.fail_next("/album/opus-eponymous", MockResponse::status(500));
+    }
+    let scraper = scraper_for(&server, ScraperState::new());
+    assert!(matches!(scraper.discography(&artist_url).await, Err(bcz::Error::UnexpectedStatus { status: 500, .. })));
+    assert!(!scraper.state().artist(&artist_url).is_some_and(|artist| artist.has_been_scraped()));
+}
+
+#[tokio::test]
+async fn uses_the_configured_endpoint_paths() {
+    let server = MockBandcamp::start(3).await;
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .artist_index_path("/mirror/artist_index")
+        .discography_path("/mirror/music")
+        .requests_per_second(1000.0)
+        .build();
+
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..3));
+
+    let artist_url = ArtistUrl {
+        name: "Artist 0".to_owned(),
+        url: server.artist_url(0),
+        band_id: None,
+    };
+    assert_eq!(scraper.artist_releases(&artist_url).await.unwrap().count(), 2);
+
+    let requests = server.requests();
+    assert!(requests.iter().any(|r| r.starts_with("/mirror/artist_index?")));
+    assert!(requests.contains(&"/mirror/music".to_owned()));
+    assert!(!requests.iter().any(|r| r.starts_with("/artist_index") || r == "/music"));
+}
+
+#[tokio::test]
+async fn serves_fresh_pages_from_the_cache_and_revalidates_stale_ones() {
+    let server = MockBandcamp::start(1).await;
+    let cache_dir = std::env::temp_dir().join(format!("bcz-mock-cache-test-{}", std::process::id()));
+    let scraper_with_cache = |cache_policy: CachePolicy| BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .cache(cache_policy)
+        .build();
+    let artist_url = ArtistUrl {
+        name: "Artist 0".to_owned(),
+        url: server.artist_url(0),
+        band_id: None,
+    };
+    let music_requests = || server.requests().iter().filter(|r| *r == "/music").count();
+
+    let scraper = scraper_with_cache(CachePolicy::new(&cache_dir));
+    assert_eq!(scraper.artist_releases(&artist_url).await.unwrap().count(), 2);
+    assert_eq!(scraper.artist_releases(&artist_url).await.unwrap().count(), 2);
+    assert_eq!(music_requests(), 1);
+
+    // Everything is stale right away, so the page gets revalidated and the server only answers with a 304.
+    let scraper = scraper_with_cache(CachePolicy {
+        artist_ttl: Duration::ZERO,
+        ..CachePolicy::new(&cache_dir)
+    });
+    assert_eq!(scraper.artist_releases(&artist_url).await.unwrap().count(), 2);
+    assert_eq!(music_requests(), 2);
+    assert_eq!(server.conditional_requests(), vec!["/music"]);
+
+    // Failing to write down the revalidation doesn't make the page we have any less valid.
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .cache(CachePolicy {
+            artist_ttl: Duration::ZERO,
+            ..CachePolicy::new(&cache_dir)
+        })
+        .fetcher(LosesCacheOnNotModified {
+            inner: ReqwestFetcher::new(reqwest::Client::new()),
+            cache_dir: cache_dir.clone(),
+        })
+        .build();
+    assert_eq!(scraper.artist_releases(&artist_url).await.unwrap().count(), 2);
+    assert_eq!(server.conditional_requests(), vec!["/music", "/music"]);
+    assert!(!cache_dir.exists());
+}
+
+#[tokio::test]
+async fn replays_an_archived_crawl_without_the_network() {
+    let server = MockBandcamp::start(13).await;
+    let warc_dir = std::env::temp_dir().join(format!("bcz-mock-warc-test-{}", std::process::id()));
+    let artist_url = ArtistUrl {
+        name: "Artist 0".to_owned(),
+        url: server.artist_url(0),
+        band_id: None,
+    };
+
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .warc(WarcPolicy::new(&warc_dir))
+        .build();
+    assert_eq!(collect_artist_names(&scraper).await, artist_names(0..13));
+    let discography = scraper.discography(&artist_url).await.unwrap();
+    let requests_while_archiving = server.requests().len();
+
+    let replayed = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .fetcher(ReplayFetcher::from_warc_dir(&warc_dir, ReplayMiss::Fail).unwrap())
+        .build();
+    assert_eq!(collect_artist_names(&replayed).await, artist_names(0..13));
+    let replayed_discography = replayed.discography(&artist_url).await.unwrap();
+    assert_eq!(replayed_discography.albums.len(), discography.albums.len());
+    assert_eq!(replayed_discography.singles.len(), discography.singles.len());
+    assert_eq!(server.requests().len(), requests_while_archiving);
+
+    let missing_artist = ArtistUrl {
+        url: server.artist_url(1),
+        ..artist_url
+    };
+    assert!(matches!(replayed.discography(&missing_artist).await, Err(bcz::Error::NotArchived { .. })));
+
+    std::fs::remove_dir_all(&warc_dir).unwrap();
+}
+
+#[tokio::test]
+async fn crawls_every_artist_and_their_releases() {
+    let server = MockBandcamp::start(13).await;
+    server.state.lock().unwrap().delay = Duration::from_millis(30);
+    let scraper = scraper_for(&server, ScraperState::new());
+    let limits = CrawlLimits { artists: 3, releases: 2, ..Default::default() };
+
+    let mut artists: Vec<_> = scraper.crawl(limits)
+        .map(|artist| artist.unwrap())
+        .collect()
+        .await;
+    artists.sort_by_key(|artist| artist.name[7..].parse::<usize>().unwrap());
+
+    assert_eq!(artists.iter().map(|a| a.name.clone()).collect::<Vec<_>>(), artist_names(0..13));
+    for artist in &artists {
+        assert_eq!(artist.discography.albums.len(), 1);
+        assert_eq!(artist.discography.singles.len(), 1);
+        assert!(artist.has_been_scraped());
+    }
+
+    let state = scraper.state();
+    assert!(state.artist_index_completed);
+    assert!(state.unscraped_artists().is_empty());
+
+    // Each stage gets as busy as its limit allows, but never busier.
+    assert_eq!(server.max_in_flight("artist"), limits.artists);
+    assert_eq!(server.max_in_flight("release"), limits.releases);
+}
+
+#[tokio::test]
+async fn crawls_stop_picking_up_artists_at_the_maximum() {
+    let server = MockBandcamp::start(13).await;
+    let scraper = scraper_for(&server, ScraperState::new());
+    let limits = CrawlLimits { artists: 3, releases: 2, max_artists: Some(4) };
+
+    let artists: Vec<_> = scraper.crawl(limits).map(|artist| artist.unwrap()).collect().await;
+
+    assert_eq!(artists.len(), 4);
+    {
+        let state = scraper.state();
+        assert_eq!(state.artists.len(), 4);
+        assert_eq!(state.next_artist_number, 4);
+        assert!(state.unscraped_artists().is_empty());
+    }
+
+    // Nothing at all is picked up with a maximum of 0.
+    let scraper = scraper_for(&server, ScraperState::new());
+    let requests = server.requests().len();
+    assert_eq!(scraper.crawl(CrawlLimits { max_artists: Some(0), ..Default::default() }).collect::<Vec<_>>().await.len(), 0);
+    assert_eq!(server.requests().len(), requests);
+}
+
+#[tokio::test]
+async fn stopping_a_crawl_finishes_the_artists_it_started() {
+    let server = MockBandcamp::start(13).await;
+    let scraper = scraper_for(&server, ScraperState::new());
+    let stop = Notify::new();
+
+    let crawl = scraper.crawl_until(CrawlLimits { artists: 3, releases: 2, ..Default::default() }, stop.notified());
+    tokio::pin!(crawl);
+    let mut artists = Vec::new();
+    while let Some(artist) = crawl.next().await {
+        artists.push(artist.unwrap());
+        stop.notify_one();
+    }
+
+    // Every artist the crawl picked up made it into the state, scraped.
+    let state = scraper.state();
+    assert!(!artists.is_empty() && artists.len() < 13);
+    assert_eq!(state.artists.len(), artists.len());
+    assert!(state.unscraped_artists().is_empty());
+    assert!(!state.artist_index_completed);
+}
+
+#[tokio::test]
+async fn crawl_picks_up_artists_left_unscraped() {
+    let server = MockBandcamp::start(3).await;
+    let mut state = ScraperState::new();
+    state.new_artist_from_url(ArtistUrl {
+        name: "Artist 1".to_owned(),
+        url: server.artist_url(1),
+        band_id: None,
+    }).unwrap();
+    state.next_artist_number = 3;
+    state.artist_index_completed = true;
+    let scraper = scraper_for(&server, state);
+
+    let artists: Vec<_> = scraper.crawl(CrawlLimits::default()).collect().await;
+
+    assert_eq!(artists.len(), 1);
+    assert_eq!(artists[0].as_ref().unwrap().name, "Artist 1");
+    assert!(!server.requests().iter().any(|r| r.starts_with("/artist_index")));
+}
+
+#[tokio::test]
+async fn custom_domain_artists_are_stored_under_their_subdomain() {
+    let server = MockBandcamp::start(3).await;
+    server.state.lock().unwrap().custom_domains = true;
+    let scraper = BcScraper::builder()
+        .base_url(server.base_url())
+        .requests_per_second(1000.0)
+        .fetcher(SubdomainsToMock {
+            inner: ReqwestFetcher::new(reqwest::Client::new()),
+            addr: server.addr,
+        })
+        .build();
+
+    let artists: Vec<_> = scraper.crawl(CrawlLimits::default()).map(|artist| artist.unwrap()).collect().await;
+    assert_eq!(artists.len(), 3);
+
+    {
+        let state = scraper.state();
+        let mut keys: Vec<_> = state.artists.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["https://artist-0.bandcamp.com", "https://artist-1.bandcamp.com", "https://artist-2.bandcamp.com"]);
+        assert!(state.unscraped_artists().is_empty());
+        assert_eq!(state.artist_by_band_id(1001).unwrap().url, "https://artist-1.bandcamp.com");
+    }
+
+    // Nothing is left for the next crawl to pick up.
+    let requests = server.requests().len();
+    assert_eq!(scraper.crawl(CrawlLimits::default()).collect::<Vec<_>>().await.len(), 0);
+    assert_eq!(server.requests().len(), requests);
+}
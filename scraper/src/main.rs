@@ -1,22 +1,91 @@
-use std::{path::{PathBuf, Path}};
+use std::{path::{PathBuf, Path}, sync::atomic::{AtomicBool, Ordering}, time::Duration};
 
-use bcz::{ScraperState, bc::BcScraper};
+use bcz::{CachePolicy, CheckpointPolicy, ScraperState, Url, WarcPolicy, bc::{BcScraper, CrawlLimits}, fetch::{ReplayFetcher, ReplayMiss}};
 use clap::Parser;
 use tokio_stream::StreamExt;
-use tracing::{info, Instrument, Level, metadata::LevelFilter};
+use tracing::{info, warn, Instrument};
 use tracing_subscriber::EnvFilter;
 
+// Exit code for a clean stop after SIGINT/SIGTERM, so supervisors can tell it apart from a crash (1) or a finished crawl (0).
+const INTERRUPTED_EXIT_CODE: i32 = 3;
+// Exit code when a second signal cut the clean stop short. Whatever wasn't checkpointed yet is lost.
+const FORCED_EXIT_CODE: i32 = 4;
+
 #[derive(Parser, Debug)]
 struct Args {
     #[clap(short, long, parse(from_os_str))]
     state_path: PathBuf,
     #[clap(short, long)]
     resume: bool,
+    // Start a new crawl even though the state file exists, replacing it on the first checkpoint.
+    #[clap(long, conflicts_with = "resume")]
+    overwrite: bool,
+    // Scrape at most this many artists, counting the ones left over from the crawl we're resuming.
+    #[clap(long)]
+    max_artists: Option<usize>,
+    #[clap(long, default_value = "100")]
+    checkpoint_every_artists: usize,
+    #[clap(long, default_value = "60")]
+    checkpoint_every_secs: u64,
+    // Scrape a mirror or proxy instead of bandcamp.com.
+    #[clap(long)]
+    base_url: Option<Url>,
+    // Keep fetched pages here and reuse them on the next run.
+    #[clap(long, parse(from_os_str))]
+    cache_dir: Option<PathBuf>,
+    // Archive every fetched page to WARC files in here.
+    #[clap(long, parse(from_os_str))]
+    warc_dir: Option<PathBuf>,
+    // Parse the pages archived in this WARC directory instead of fetching anything. Pages that weren't archived are treated as missing.
+    #[clap(long, parse(from_os_str))]
+    replay_warc_dir: Option<PathBuf>,
+    // Artists scraped at the same time.
+    #[clap(long, default_value = "4")]
+    concurrent_artists: usize,
+    // Release pages fetched at the same time, across all artists.
+    #[clap(long, default_value = "8")]
+    concurrent_releases: usize,
+}
+
+fn read_or_create_state<P: AsRef<Path>>(state_path: P, resume: bool, overwrite: bool) -> Result<ScraperState, Box<dyn std::error::Error>> {
+    let state_path = state_path.as_ref();
+
+    if !resume {
+        // The first checkpoint would replace what could be days of crawling.
+        if state_path.exists() {
+            if !overwrite {
+                return Err(format!("{} already exists, pass --resume to continue that crawl or --overwrite to start over", state_path.display()).into());
+            }
+
+            warn!(state_path = %state_path.display(), "Not resuming, so the existing state will be overwritten.");
+        }
+
+        info!("Starting a new state");
+        return Ok(ScraperState::new());
+    }
+
+    info!(state_path = %state_path.display(), "Resuming from an existing state");
+    Ok(ScraperState::load_from_file(state_path)?)
 }
 
-fn read_or_create_state<P: AsRef<Path>>(state_path: P) -> Result<ScraperState, Box<dyn std::error::Error>> {
-    let state_string = std::fs::read_to_string(state_path)?;
-    Ok(serde_json::from_str(&state_string)?)
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("Got SIGINT"),
+            _ = sigterm.recv() => info!("Got SIGTERM"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl-C");
+        info!("Got Ctrl-C");
+    }
 }
 
 #[tokio::main]
@@ -28,21 +97,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt()
         .with_env_filter(filter)
         .init();
-    info!("Starting a new state");
-    // let state = read_or_create_state(args.state_path)?;
-    let state = ScraperState::new();
-    let scraper = BcScraper::with_state(state);
+    let state = read_or_create_state(&args.state_path, args.resume, args.overwrite)?;
+    info!(next_artist_number = state.next_artist_number, artists = state.artists.len(), "Loaded the state");
+    let mut builder = BcScraper::builder().state(state);
 
-    let mut artists = scraper.artists();
-    let mut total_artists = 1000;
+    if let Some(base_url) = args.base_url {
+        builder = builder.base_url(base_url);
+    }
 
-    while let Some(artist_url) = artists.next().instrument(tracing::info_span!("artists_stream")).await {
-        info!(artist_url = ?artist_url, "Got a new artist!");
-        total_artists -= 1;
+    if let Some(cache_dir) = args.cache_dir {
+        builder = builder.cache(CachePolicy::new(cache_dir));
+    }
+
+    if let Some(warc_dir) = args.warc_dir {
+        builder = builder.warc(WarcPolicy::new(warc_dir));
+    }
+
+    if let Some(replay_warc_dir) = args.replay_warc_dir {
+        // Nothing goes over the network, so there's no reason to wait between requests.
+        builder = builder
+            .fetcher(ReplayFetcher::from_warc_dir(replay_warc_dir, ReplayMiss::Skip)?)
+            .requests_per_second(1000.0);
+    }
+
+    let scraper = builder
+        .checkpoint(CheckpointPolicy {
+            path: args.state_path.clone(),
+            every_artists: Some(args.checkpoint_every_artists),
+            every: Some(Duration::from_secs(args.checkpoint_every_secs)),
+        })
+        .build();
+
+    let interrupted = AtomicBool::new(false);
+    // Once this resolves, the crawl stops picking up new artists but still finishes the ones it's scraping, so they make it into the state before we save it.
+    let stop = async {
+        shutdown_signal().await;
+        interrupted.store(true, Ordering::Relaxed);
+        info!("Stopping the crawl once the artists being scraped are done. Send another signal to stop right away.");
+
+        // Finishing the artists can take minutes when the server wants us to back off, so that's the way out.
+        tokio::spawn(async {
+            shutdown_signal().await;
+            warn!("Got a second signal, exiting without waiting for the artists being scraped.");
+            std::process::exit(FORCED_EXIT_CODE);
+        });
+    };
 
-        if total_artists <= 0 {
-            break;
+    let crawl = scraper.crawl_until(CrawlLimits {
+        artists: args.concurrent_artists,
+        releases: args.concurrent_releases,
+        max_artists: args.max_artists,
+    }, stop);
+    tokio::pin!(crawl);
+    let mut artists_crawled = 0;
+
+    while let Some(artist) = crawl.next().instrument(tracing::info_span!("crawl")).await {
+        // Errors are already logged by the crawl, and the artist will be tried again on the next one.
+        if let Ok(artist) = artist {
+            info!(artist_url = %artist.url, albums = artist.discography.albums.len(), eps = artist.discography.eps.len(), singles = artist.discography.singles.len(), "Scraped an artist!");
         }
+
+        artists_crawled += 1;
+    }
+
+    info!(state_path = %args.state_path.display(), "Saving the state");
+    scraper.checkpoint()?;
+
+    if interrupted.load(Ordering::Relaxed) {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+
+    // Otherwise the crawl only runs out early when the artist index gave up, so the rest of it is still ahead of us.
+    let reached_max_artists = args.max_artists.is_some_and(|max_artists| artists_crawled >= max_artists);
+    if !reached_max_artists && !scraper.state().artist_index_completed {
+        return Err("gave up on the artist index before reaching its end, resume to try again".into());
     }
 
     Ok(())